@@ -4,5 +4,7 @@ cargo build --release
 mkdir lists
 mkdir archive
 mkdir apt
+mkdir trusted.gpg.d
+cp /usr/share/keyrings/ubuntu-archive-keyring.gpg trusted.gpg.d/
 ln -s $(pwd)/target/release/rapt $(pwd)/rapt
 ./rapt update
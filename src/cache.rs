@@ -1,113 +1,104 @@
+use crate::config::CONFIG;
 use crate::lock::{get_lock, Lock};
 use crate::slist;
-use crate::source::{self, SourcePackage, CACHE};
+use crate::source::{self, Arch, SourcePackage, CACHE, CACHE_PROVIDERS};
 use glob::Pattern;
 use std::fs;
 use std::io::Write;
 use std::path::Path;
 
 pub fn get_info_from_filename(filename: &str) -> (String, String) {
-  let tmp = filename.split("_dists_").collect::<Vec<_>>();
-  let part = match tmp.iter().nth(1) {
-    Some(_part) => _part,
-    None => return ("".to_string(), "".to_string()),
-  };
-  let ix = match part.rfind("-") {
-    Some(_ix) => _ix,
-    None => return ("".to_string(), "".to_string()),
-  };
-  let dist = String::from(&part[..ix]);
-  let component = String::from(&part[ix + 1..]);
+  match slist::split_filename(filename) {
+    Some((_, dist, component)) => (dist, component),
+    None => ("".to_string(), "".to_string()),
+  }
+}
 
-  (dist, component)
+// index files named in older formats can't tell where packages come from.
+// they are ignored until 'rapt update' replaces them.
+fn is_legacy_list(filename: &str) -> bool {
+  match slist::split_filename(filename) {
+    Some((base, _, _)) => !base.contains("://"),
+    None => true,
+  }
 }
 
-pub fn get_pool_domain(package: &SourcePackage) -> Result<String, ()> {
-  match glob::glob("lists/*") {
-    Ok(paths) => {
-      for entry in paths {
-        match entry {
-          Ok(path) => {
-            if path.is_dir() || path.file_name().unwrap().to_str().unwrap() == "lock" {
-              continue;
-            }
-            let raw_cache = match std::fs::read_to_string(&path) {
-              Ok(_raw_cache) => _raw_cache,
-              Err(msg) => {
-                println!("{}", msg);
-                return Err(());
-              }
-            };
-            match source::SourcePackage::from_raw(
-              &raw_cache,
-              path.file_name().unwrap().to_str().unwrap(),
-            ) {
-              Ok(_items) => {
-                if _items
-                  .iter()
-                  .filter(|i| i.package == package.package)
-                  .collect::<Vec<_>>()
-                  .len()
-                  != 0
-                {
-                  let mut domain = String::new();
-                  let filename = String::from(path.file_name().unwrap().to_str().unwrap());
-                  let mut count = 0;
-                  for c in filename.chars() {
-                    if c == '_' {
-                      domain.push('/');
-                      count += 1;
-                    } else {
-                      domain.push(c);
-                    }
-                    if count == 2 {
-                      break;
-                    }
-                  }
-                  return Ok(domain);
-                }
-              }
-              Err(msg) => {
-                println!("{}", msg);
-                return Err(());
-              }
-            };
-          }
-          Err(msg) => {
-            println!("failed to open cache file: {}", msg);
-            return Err(());
-          }
-        };
-      }
+// @ret: base URI of the repository whose index @package is read from.
+pub fn get_pool_base(package: &SourcePackage) -> Result<String, String> {
+  match slist::split_filename(&package.list) {
+    Some((base, _, _)) if !is_legacy_list(&package.list) => Ok(base),
+    _ => Err(format!(
+      "repository of {} {} is unknown.",
+      package.qualified_name(),
+      package.version
+    )),
+  }
+}
+
+// remove index files in lists/ other than @keeps, which are left by removed sources
+// or named in old format.
+pub fn remove_stale_lists(keeps: &[String]) -> Result<(), String> {
+  let paths = match glob::glob("lists/*") {
+    Ok(_paths) => _paths,
+    Err(_) => return Err("invalid glob pattern.".to_string()),
+  };
+  for path in paths.flatten() {
+    let filename = path.file_name().unwrap().to_str().unwrap().to_string();
+    if path.is_dir() || filename == "lock" || keeps.contains(&filename) {
+      continue;
     }
-    Err(_) => {
-      println!("invalid glob pattern.");
-      return Err(());
+    log::info!("removing stale cache file: lists/{}", filename);
+    if let Err(msg) = fs::remove_file(&path) {
+      return Err(format!("failed to remove lists/{}: {}", filename, msg));
     }
-  };
-  return Err(());
+  }
+
+  Ok(())
 }
 
+// @names may be qualified by architecture as 'pkg:arch'.
 pub fn search_cache_with_names(names: &Vec<String>) -> Vec<SourcePackage> {
   let mut ret_items = vec![];
   for name in names {
-    let name_glob = glob::Pattern::new(name.split(":").collect::<Vec<_>>()[0]).unwrap();
-    let mut founds = search_cache_with_name_glob(&name_glob, true);
-    ret_items.append(&mut founds);
+    let (name, arch) = source::split_qualified_name(name);
+    let name_glob = glob::Pattern::new(name).unwrap();
+    let founds = search_cache_with_name_glob(&name_glob, true);
+    ret_items.extend(founds.into_iter().filter(|p| match arch {
+      Some(arch) if Arch::from_name(arch) != Arch::UNKNOWN && arch != "any" => p.matches_arch(arch),
+      _ => true,
+    }));
   }
 
   ret_items
 }
 
+// @ret: packages in the cache which satisfy dependency on @dep('pkg' or 'pkg:qualifier')
+//       from a package of @depender_arch, including providers of virtual @dep.
+//       real packages come first, and then candidates of @depender_arch.
+pub fn search_cache_for_dep(dep: &str, depender_arch: &str) -> Vec<SourcePackage> {
+  let (name, qualifier) = source::split_qualified_name(dep);
+  let cached_items = &*CACHE;
+  let mut founds = cached_items
+    .iter()
+    .filter(|p| p.package == name)
+    .chain(source::find_providers(cached_items, &CACHE_PROVIDERS, name))
+    .filter(|p| p.satisfies_arch(qualifier, depender_arch))
+    .cloned()
+    .collect::<Vec<_>>();
+  founds.sort_by_key(|p| (p.package != name, p.arch_name() != depender_arch));
+
+  founds
+}
+
 // @cache should be resolved in duplication.
-// XXX for now, return items whose 'Provides' matches.
+// items whose 'Provides' matches are returned, too.
 pub fn search_cache_with_name_glob(glob: &Pattern, case_sensitive: bool) -> Vec<SourcePackage> {
   let mut ret_items = vec![];
   let cached_items = &*CACHE;
 
   for item in cached_items {
     if case_sensitive {
-      if glob.matches(&item.package) || item.provides.iter().any(|d| glob.matches(d)) {
+      if glob.matches(&item.package) || item.provides.iter().any(|d| glob.matches(&d.name)) {
         ret_items.push(item.clone());
       }
     } else {
@@ -115,7 +106,7 @@ pub fn search_cache_with_name_glob(glob: &Pattern, case_sensitive: bool) -> Vec<
         || item
           .provides
           .iter()
-          .any(|d| glob.matches(&d.to_lowercase()))
+          .any(|d| glob.matches(&d.name.to_lowercase()))
       {
         ret_items.push(item.clone());
       }
@@ -172,6 +163,13 @@ pub fn get_cached_items() -> Vec<SourcePackage> {
             if filename == "lock" {
               continue;
             }
+            if is_legacy_list(filename.to_str().unwrap()) {
+              println!(
+                "ignoring cache file in old format: {}. do 'rapt update'.",
+                filename.to_str().unwrap()
+              );
+              continue;
+            }
             let raw_cache = match std::fs::read_to_string(&path) {
               Ok(_raw_cache) => _raw_cache,
               Err(msg) => {
@@ -181,7 +179,15 @@ pub fn get_cached_items() -> Vec<SourcePackage> {
               }
             };
             match source::SourcePackage::from_raw(&raw_cache, filename.to_str().unwrap()) {
-              Ok(mut _items) => ret_items.append(&mut _items),
+              Ok(_items) => {
+                // flat repository may have packages of unconfigured architectures.
+                let archs = CONFIG.architectures();
+                ret_items.extend(
+                  _items
+                    .into_iter()
+                    .filter(|i| archs.iter().any(|a| i.matches_arch(a))),
+                )
+              }
               Err(msg) => {
                 lock.unlock().unwrap();
                 println!("{}", msg);
@@ -208,8 +214,8 @@ pub fn get_cached_items() -> Vec<SourcePackage> {
   ret_items
 }
 
-pub fn write_cache_raw(raw_index: &str, source: &slist::Source) -> Result<(), String> {
-  let filename = source.to_filename();
+pub fn write_cache_raw(raw_index: &str, source: &slist::Source, arch: &str) -> Result<(), String> {
+  let filename = source.to_filename(arch);
   if !Path::new("lists").exists() {
     return Err("cache directory 'lists' doesn't exist. aborting...".to_string());
   };
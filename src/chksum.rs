@@ -0,0 +1,121 @@
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::io::prelude::*;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HashKind {
+  MD5,
+  SHA1,
+  SHA256,
+}
+
+impl std::fmt::Display for HashKind {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::MD5 => write!(f, "MD5Sum"),
+      Self::SHA1 => write!(f, "SHA1"),
+      Self::SHA256 => write!(f, "SHA256"),
+    }
+  }
+}
+
+// @ret: lowercase hex digest of @data
+pub fn hash_hex(kind: HashKind, data: &[u8]) -> String {
+  match kind {
+    HashKind::MD5 => hex::encode(Md5::digest(data)),
+    HashKind::SHA1 => hex::encode(Sha1::digest(data)),
+    HashKind::SHA256 => hex::encode(Sha256::digest(data)),
+  }
+}
+
+// calculates all kinds of digests at once while data is being streamed.
+#[derive(Default, Clone)]
+pub struct Hasher {
+  md5: Md5,
+  sha1: Sha1,
+  sha256: Sha256,
+  size: u64,
+}
+
+impl Hasher {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn update(&mut self, data: &[u8]) {
+    self.md5.update(data);
+    self.sha1.update(data);
+    self.sha256.update(data);
+    self.size += data.len() as u64;
+  }
+
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+
+  // @ret: lowercase hex digest of data fed so far
+  pub fn hex(&self, kind: HashKind) -> String {
+    match kind {
+      HashKind::MD5 => hex::encode(self.md5.clone().finalize()),
+      HashKind::SHA1 => hex::encode(self.sha1.clone().finalize()),
+      HashKind::SHA256 => hex::encode(self.sha256.clone().finalize()),
+    }
+  }
+}
+
+// feed the whole content of @path into a new Hasher.
+pub fn hash_file(path: &str) -> Result<Hasher, String> {
+  let mut file = match std::fs::File::open(path) {
+    Ok(_file) => _file,
+    Err(msg) => return Err(format!("failed to open {}: {}", path, msg)),
+  };
+  let mut hasher = Hasher::new();
+  let mut buf = [0u8; 8192];
+  loop {
+    match file.read(&mut buf) {
+      Ok(0) => break,
+      Ok(n) => hasher.update(&buf[..n]),
+      Err(msg) => return Err(format!("failed to read {}: {}", path, msg)),
+    }
+  }
+
+  Ok(hasher)
+}
+
+#[cfg(test)]
+pub mod test {
+  #[test]
+  fn test_hash_hex() {
+    use super::{hash_hex, HashKind};
+    assert_eq!(
+      hash_hex(HashKind::MD5, b"rapt"),
+      "69f971655c0353062dbaf788cf5022e9"
+    );
+    assert_eq!(
+      hash_hex(HashKind::SHA256, b""),
+      "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+  }
+
+  #[test]
+  fn test_hasher() {
+    use super::{hash_hex, HashKind, Hasher};
+    let data = std::fs::read("test/sample-index").unwrap();
+    let mut hasher = Hasher::new();
+    for chunk in data.chunks(100) {
+      hasher.update(chunk);
+    }
+    assert_eq!(hasher.size(), data.len() as u64);
+    for kind in &[HashKind::MD5, HashKind::SHA1, HashKind::SHA256] {
+      assert_eq!(hasher.hex(*kind), hash_hex(*kind, &data));
+    }
+
+    let file_hasher = super::hash_file("test/sample-index").unwrap();
+    assert_eq!(file_hasher.size(), hasher.size());
+    assert_eq!(
+      file_hasher.hex(HashKind::SHA256),
+      hasher.hex(HashKind::SHA256)
+    );
+  }
+}
@@ -1,11 +1,58 @@
 use clap::{App, Arg, ArgGroup, SubCommand};
 
+// options shared by subcommands which install packages.
+fn install_args() -> Vec<Arg<'static, 'static>> {
+  vec![
+    Arg::with_name("no-install-recommends")
+      .help("don't install recommended packages.")
+      .long("no-install-recommends"),
+    Arg::with_name("install-suggests")
+      .help("install suggested packages too.")
+      .long("install-suggests"),
+    Arg::with_name("solver")
+      .help("EDSP solver to resolve dependencies, or 'internal'.")
+      .long("solver")
+      .takes_value(true),
+    Arg::with_name("dump-scenario")
+      .help("write EDSP scenario into the file instead of installing packages.")
+      .long("dump-scenario")
+      .takes_value(true)
+      .conflicts_with("solver"),
+  ]
+}
+
+// arguments shared by subcommands which remove packages.
+fn remove_args() -> Vec<Arg<'static, 'static>> {
+  vec![
+    Arg::with_name("package")
+      .help("installed packages to remove")
+      .multiple(true)
+      .required(true),
+    Arg::with_name("allow-remove-essential")
+      .help("allow to remove essential packages.")
+      .long("allow-remove-essential"),
+  ]
+}
+
 pub fn build_cli() -> App<'static, 'static> {
   App::new("rapt")
     .version(env!("CARGO_PKG_VERSION"))
     .author("(c) 2021 Nirugiri")
     .subcommands(vec![
-      SubCommand::with_name("update").about("update index"),
+      SubCommand::with_name("update")
+        .about("update index")
+        .arg(
+          Arg::with_name("trusted-dir")
+            .help("directory of trusted keyrings(*.gpg) used to verify Release files.")
+            .long("trusted-dir")
+            .takes_value(true),
+        )
+        .arg(
+          Arg::with_name("source-parts")
+            .help("directory of additional source lists(*.list and deb822-style *.sources).")
+            .long("source-parts")
+            .takes_value(true),
+        ),
       SubCommand::with_name("list")
         .about("query package database and list packages.")
         .arg(
@@ -29,10 +76,54 @@ pub fn build_cli() -> App<'static, 'static> {
         .arg(Arg::with_name("package").help("target package glob to search for").required(true)),
       SubCommand::with_name("install")
         .about("install package")
-        .arg(Arg::with_name("package").help("package name or .deb file to install").required(true)),
+        .arg(Arg::with_name("package").help("package name or .deb file to install").required(true))
+        .args(&install_args()),
       SubCommand::with_name("clean")
         .about("clean archive directory"),
       SubCommand::with_name("upgrade")
-        .about("install upgradable packages")
+        .about("install upgradable packages, keeping back ones which need new packages or removals")
+        .args(&install_args()),
+      SubCommand::with_name("full-upgrade")
+        .alias("dist-upgrade")
+        .about("install upgradable packages, installing and removing packages as needed")
+        .args(&install_args()),
+      SubCommand::with_name("remove")
+        .about("remove packages and packages depending on them")
+        .args(&remove_args()),
+      SubCommand::with_name("purge")
+        .about("remove packages with their configuration files")
+        .args(&remove_args()),
+      SubCommand::with_name("mark")
+        .about("change or show whether packages are installed automatically")
+        .subcommands(vec![
+          SubCommand::with_name("auto")
+            .about("mark packages as automatically installed")
+            .arg(Arg::with_name("package").help("installed packages to mark").multiple(true).required(true)),
+          SubCommand::with_name("manual")
+            .about("mark packages as manually installed")
+            .arg(Arg::with_name("package").help("installed packages to mark").multiple(true).required(true)),
+          SubCommand::with_name("showauto")
+            .about("list automatically installed packages")
+            .arg(Arg::with_name("package").help("packages to show").multiple(true)),
+          SubCommand::with_name("showmanual")
+            .about("list manually installed packages")
+            .arg(Arg::with_name("package").help("packages to show").multiple(true)),
+          SubCommand::with_name("hold")
+            .about("hold packages back from upgrades and removals")
+            .arg(Arg::with_name("package").help("installed packages to hold").multiple(true).required(true)),
+          SubCommand::with_name("unhold")
+            .about("cancel holds of packages")
+            .arg(Arg::with_name("package").help("installed packages to unhold").multiple(true).required(true)),
+          SubCommand::with_name("showhold")
+            .about("list packages on hold")
+            .arg(Arg::with_name("package").help("packages to show").multiple(true)),
+        ]),
+      SubCommand::with_name("autoremove")
+        .about("remove automatically installed packages which are no longer needed")
+        .arg(
+          Arg::with_name("dry-run")
+            .help("only list packages to be removed.")
+            .long("dry-run"),
+        ),
     ])
 }
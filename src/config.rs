@@ -0,0 +1,179 @@
+use crate::source::Arch;
+use once_cell::sync::Lazy;
+
+pub const DEFAULT_CONFIG_FILE: &str = "rapt.conf";
+
+pub static CONFIG: Lazy<Config> = Lazy::new(|| match Config::from_file(DEFAULT_CONFIG_FILE) {
+  Ok(config) => config,
+  Err(msg) => {
+    println!("{}", msg);
+    std::process::exit(1);
+  }
+});
+
+// configuration read from 'rapt.conf', written in deb822-like 'Key: value' format.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Config {
+  // CA bundle(PEM) trusted in addition to the system's one for https:// sources.
+  pub ca_info: Option<String>,
+  // native architecture, and foreign ones whose packages can be installed too.
+  pub architecture: String,
+  pub foreign_architectures: Vec<String>,
+  // default of '--no-install-recommends' and '--install-suggests'.
+  pub install_recommends: bool,
+  pub install_suggests: bool,
+  // EDSP solver used instead of the internal resolver.
+  pub external_solver: Option<String>,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      ca_info: None,
+      architecture: host_architecture(),
+      foreign_architectures: vec![],
+      install_recommends: true,
+      install_suggests: false,
+      external_solver: None,
+    }
+  }
+}
+
+// @ret: architecture of the host in Debian's name, reported by 'dpkg --print-architecture'.
+//       the one rapt is built for is used if dpkg is unavailable.
+pub fn host_architecture() -> String {
+  if let Ok(output) = std::process::Command::new("dpkg")
+    .arg("--print-architecture")
+    .output()
+  {
+    let arch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status.success() && !arch.is_empty() {
+      return arch;
+    }
+  }
+  build_architecture()
+}
+
+// @ret: architecture rapt is built for, in Debian's name.
+fn build_architecture() -> String {
+  match std::env::consts::ARCH {
+    "x86_64" => "amd64",
+    "x86" => "i386",
+    "aarch64" => "arm64",
+    "arm" => "armhf",
+    "powerpc64" => "ppc64el",
+    "powerpc" => "powerpc",
+    "s390x" => "s390x",
+    "mips64" => "mips64el",
+    "mips" => "mipsel",
+    "riscv64" => "riscv64",
+    "loongarch64" => "loong64",
+    "sparc64" => "sparc64",
+    other => other,
+  }
+  .to_string()
+}
+
+impl Config {
+  // @ret: native architecture followed by foreign ones.
+  pub fn architectures(&self) -> Vec<String> {
+    let mut archs = vec![self.architecture.clone()];
+    for arch in &self.foreign_architectures {
+      if !archs.contains(arch) {
+        archs.push(arch.clone());
+      }
+    }
+    archs
+  }
+
+  // missing file is not an error, and default configuration is used.
+  pub fn from_file(filename: &str) -> Result<Self, String> {
+    if !std::path::Path::new(filename).exists() {
+      return Ok(Self::default());
+    }
+    match std::fs::read_to_string(filename) {
+      Ok(raw) => Self::from_raw(&raw).map_err(|msg| format!("{}: {}", filename, msg)),
+      Err(msg) => Err(format!("Failed to open config file {}: {}", filename, msg)),
+    }
+  }
+
+  pub fn from_raw(raw: &str) -> Result<Self, String> {
+    let mut config = Self::default();
+    for (ix, line) in raw.split("\n").enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let kv = line.splitn(2, ":").collect::<Vec<_>>();
+      if kv.len() != 2 {
+        return Err(format!("line {}: malformed config: {}", ix + 1, line));
+      }
+      let value = kv[1].trim().to_string();
+      let check_arch = |arch: &str| match Arch::from_name(arch) {
+        Arch::UNKNOWN | Arch::ALL | Arch::ANY => {
+          Err(format!("line {}: unknown architecture: {}", ix + 1, arch))
+        }
+        _ => Ok(arch.to_string()),
+      };
+      let boolean = |value: &str| match value {
+        "yes" | "true" => Ok(true),
+        "no" | "false" => Ok(false),
+        _ => Err(format!("line {}: invalid boolean: {}", ix + 1, value)),
+      };
+      match kv[0].trim() {
+        "CA-Info" => config.ca_info = Some(value),
+        "Install-Recommends" => config.install_recommends = boolean(&value)?,
+        "Install-Suggests" => config.install_suggests = boolean(&value)?,
+        "External-Solver" => config.external_solver = Some(value),
+        "Architecture" => config.architecture = check_arch(&value)?,
+        "Foreign-Architectures" => {
+          config.foreign_architectures = value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|a| !a.is_empty())
+            .map(check_arch)
+            .collect::<Result<Vec<_>, _>>()?
+        }
+        key => return Err(format!("line {}: unknown config: {}", ix + 1, key)),
+      }
+    }
+
+    Ok(config)
+  }
+}
+
+#[cfg(test)]
+pub mod test {
+  #[test]
+  fn test_config_from_raw() {
+    use super::Config;
+    let config = Config::from_raw("# comment\nCA-Info: /etc/ssl/internal.pem\n").unwrap();
+    assert_eq!(config.ca_info, Some("/etc/ssl/internal.pem".to_string()));
+    assert_eq!(Config::from_raw("").unwrap(), Config::default());
+    assert!(Config::from_raw("Unknown-Key: yes").is_err());
+    assert!(Config::from_raw("CA-Info").is_err());
+
+    let config =
+      Config::from_raw("Architecture: arm64\nForeign-Architectures: i386, armhf").unwrap();
+    assert_eq!(config.architecture, "arm64");
+    assert_eq!(
+      config.architectures(),
+      vec!["arm64".to_string(), "i386".to_string(), "armhf".to_string()]
+    );
+    assert!(Config::from_raw("Architecture: pdp11").is_err());
+
+    let config = Config::from_raw("Install-Recommends: no\nInstall-Suggests: yes").unwrap();
+    assert!(!config.install_recommends && config.install_suggests);
+    assert!(Config::default().install_recommends);
+    assert!(Config::from_raw("Install-Recommends: maybe").is_err());
+    let config = Config::from_raw("External-Solver: /usr/lib/apt/solvers/apt").unwrap();
+    assert_eq!(
+      config.external_solver,
+      Some("/usr/lib/apt/solvers/apt".to_string())
+    );
+    assert!(Config::from_raw("Foreign-Architectures: all").is_err());
+    assert_eq!(
+      Config::from_file("test/no-such-rapt.conf").unwrap(),
+      Config::default()
+    );
+  }
+}
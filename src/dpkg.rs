@@ -1,19 +1,42 @@
-use crate::cache;
-use crate::source::{self, SourcePackage, DPKG_CACHE};
+use crate::fetcher;
+use crate::source::{self, RelOp, SourcePackage, DPKG_CACHE};
 use crate::version::*;
 use colored::*;
 use glob::Pattern;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::process::{Command, Stdio};
-use std::sync::mpsc::{self, TryRecvError};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+
+// whole output of dpkg runs is kept here.
+pub const DPKG_LOG: &str = "log/term.log";
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PackageState {
   MISSING,
   OLD,
   UPTODATE,
+  // installed version is newer than the upper bound of the relation.
+  CONFLICT,
+}
+
+// which kinds of weak dependencies are pulled into the install plan.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DependencyFlags {
+  pub recommends: bool,
+  pub suggests: bool,
+}
+
+impl Default for DependencyFlags {
+  fn default() -> Self {
+    Self {
+      recommends: true,
+      suggests: false,
+    }
+  }
 }
 
 pub fn read_dpkg_state() -> Result<Vec<SourcePackage>, String> {
@@ -42,9 +65,14 @@ pub fn check_upgradable(
       _progress_bar.unwrap().set_message(ditem.package.clone());
       _progress_bar.unwrap().inc(1);
     }
+    // removed packages are not upgraded even if their configuration files are left.
+    if ditem.status.status != source::StatusStatus::INSTALLED {
+      continue;
+    }
+    // the same package of another architecture is not an upgrade.
     let iitems = index_items
       .iter()
-      .filter(|item| item.package == ditem.package)
+      .filter(|item| item.package == ditem.package && item.matches_arch(&ditem.arch_name()))
       .collect::<Vec<_>>();
     if iitems.len() == 0 {
       log::warn!(
@@ -67,9 +95,12 @@ pub fn check_upgradable(
   Ok(upgradable_items)
 }
 
+// @_package_name may be qualified by architecture as 'pkg:arch', and installed packages
+// are checked against it under Multi-Arch rules as a dependency of @depender_arch package.
 pub fn check_missing_or_old(
   _package_name: &str,
-  package_version: &Option<String>,
+  package_version: &Option<(RelOp, String)>,
+  depender_arch: &str,
   _progress_bar: Option<&ProgressBar>,
 ) -> Result<PackageState, String> {
   let installed_items = &*source::DPKG_CACHE;
@@ -87,7 +118,7 @@ pub fn check_missing_or_old(
     _progress_bar.unwrap().set_position(0);
   }
 
-  let package_name = String::from(_package_name);
+  let (package_name, qualifier) = source::split_qualified_name(_package_name);
 
   let total_installed_item = installed_items.len();
   for (ix, ditem) in installed_items.iter().enumerate() {
@@ -99,37 +130,175 @@ pub fn check_missing_or_old(
       ));
       _progress_bar.unwrap().inc(1);
     }
-    let mut dpackage_name = String::new();
-    for c in ditem.package.chars() {
-      if c.is_digit(10) {
-        break;
-      } else {
-        dpackage_name.push(c);
-      }
+    // find a package with name @package_name
+    if ditem.package == package_name && ditem.satisfies_arch(qualifier, depender_arch) {
+      finalize_progress_bar();
+      return Ok(check_version(&ditem.version, package_version));
     }
-    // find a package with name or 'Provides' is @package_name
-    if dpackage_name == package_name {
-      match package_version {
-        Some(v) => {
-          let res_cmp_version = comp_version(&ditem.version, &v);
-          if res_cmp_version >= 0 {
-            finalize_progress_bar();
-            return Ok(PackageState::UPTODATE);
-          } else {
-            finalize_progress_bar();
-            return Ok(PackageState::OLD);
-          }
-        }
-        None => {
-          finalize_progress_bar();
-          return Ok(PackageState::UPTODATE); // if required version is missing, regard it as up-to-date
-        }
+  }
+
+  // otherwise, a virtual package is satisfied by any installed provider.
+  let relation = source::Relation {
+    name: package_name.to_string(),
+    version: package_version.clone(),
+    ..Default::default()
+  };
+  let provided = source::find_providers(installed_items, &source::DPKG_PROVIDERS, package_name)
+    .into_iter()
+    .any(|p| {
+      p.status.status == source::StatusStatus::INSTALLED
+        && p.satisfies_arch(qualifier, depender_arch)
+        && relation.satisfied_by(p)
+    });
+  finalize_progress_bar();
+  if provided {
+    Ok(PackageState::UPTODATE)
+  } else {
+    Ok(PackageState::MISSING)
+  }
+}
+
+// judge @installed version against the relation @required.
+pub fn check_version(installed: &str, required: &Option<(RelOp, String)>) -> PackageState {
+  match required {
+    Some((op, version)) => {
+      let res_cmp_version = comp_version(installed, version);
+      if op.matches(res_cmp_version) {
+        return PackageState::UPTODATE;
+      }
+      match op {
+        // upgrade can satisfy the relation.
+        RelOp::GE | RelOp::GT => PackageState::OLD,
+        RelOp::EQ if res_cmp_version < 0 => PackageState::OLD,
+        // too new for the upper bound.
+        _ => PackageState::CONFLICT,
       }
     }
+    // if required version is missing, regard it as up-to-date
+    None => PackageState::UPTODATE,
   }
+}
 
-  finalize_progress_bar();
-  Ok(PackageState::MISSING)
+// @ret: whether 'Conflicts' or 'Breaks' of @package hits @other.
+// unqualified relations apply to packages of every architecture.
+pub fn conflicts_with(package: &SourcePackage, other: &SourcePackage) -> bool {
+  if package.package == other.package {
+    return false; // 'Multi-Arch: same' or self-conflict via Provides
+  }
+  let arch = package.arch_name();
+  package
+    .conflicts
+    .iter()
+    .chain(package.breaks.iter())
+    .flatten()
+    .filter(|r| r.applies_to_arch(&arch))
+    .any(|r| r.satisfied_by(other))
+}
+
+// @ret: whether @package takes over @other completely, by 'Conflicts' and 'Replaces' on it.
+//       only files are moved from @other by 'Breaks' and 'Replaces'.
+pub fn replaces(package: &SourcePackage, other: &SourcePackage) -> bool {
+  let arch = package.arch_name();
+  let declared = |groups: &[source::OrGroup]| {
+    groups
+      .iter()
+      .flatten()
+      .filter(|r| r.applies_to_arch(&arch))
+      .any(|r| r.satisfied_by(other))
+  };
+  package.package != other.package && declared(&package.conflicts) && declared(&package.replaces)
+}
+
+// remove @packages, or purge their configuration files too with @purge.
+// dpkg refuses to remove essential packages unless @force_essential.
+pub fn remove_packages(
+  packages: &[SourcePackage],
+  purge: bool,
+  force_essential: bool,
+) -> Result<(), String> {
+  let mut args = vec![if purge { "--purge" } else { "--remove" }.to_string()];
+  if force_essential {
+    args.push("--force-remove-essential".to_string());
+  }
+  args.extend(packages.iter().map(|p| p.arch_qualified_name()));
+  if purge {
+    run_dpkg(&args, "Purging    ", packages.len(), &["not-installed"])
+  } else {
+    run_dpkg(
+      &args,
+      "Removing   ",
+      packages.len(),
+      &["config-files", "not-installed"],
+    )
+  }
+}
+
+// set dpkg selections of packages, like [("pkg:arch", "hold")].
+pub fn set_selections(selections: &[(String, &str)]) -> Result<(), String> {
+  let input = selections
+    .iter()
+    .map(|(package, state)| format!("{} {}\n", package, state))
+    .collect::<String>();
+  let output = run_with_input("dpkg", &["--set-selections"], input)?;
+
+  if !output.status.success() {
+    return Err(format!(
+      "dpkg failed to set selections:\n{}",
+      String::from_utf8_lossy(&output.stderr).trim_end()
+    ));
+  }
+  Ok(())
+}
+
+// run @program with @args, feeding @input to its stdin.
+// @ret: output of the program, which may have exited with failure.
+pub fn run_with_input(program: &str, args: &[&str], input: String) -> Result<Output, String> {
+  let mut child = match Command::new(program)
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+  {
+    Ok(_child) => _child,
+    Err(msg) => return Err(format!("failed to execute {}: {}", program, msg)),
+  };
+
+  // write in another thread not to be blocked by full stdout pipe.
+  let mut stdin = child.stdin.take().unwrap();
+  let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+  let output = match child.wait_with_output() {
+    Ok(_output) => _output,
+    Err(msg) => return Err(format!("failed to wait for {}: {}", program, msg)),
+  };
+  // failure of writing shows up as the exit status of the program.
+  match writer.join() {
+    Ok(_) => Ok(output),
+    Err(_) => Err(format!("failed to write the input to {}", program)),
+  }
+}
+
+// only 'Multi-Arch: same' packages can be installed for several architectures at once.
+pub fn check_coinstallable(package: &SourcePackage) -> Result<(), String> {
+  let arch = package.arch_name();
+  for ditem in &*DPKG_CACHE {
+    if ditem.package != package.package || ditem.status.status != source::StatusStatus::INSTALLED {
+      continue;
+    }
+    let darch = ditem.arch_name();
+    if darch == arch || darch == "all" || arch == "all" {
+      continue;
+    }
+    if package.multi_arch != source::MultiArch::SAME || ditem.multi_arch != source::MultiArch::SAME
+    {
+      return Err(format!(
+        "{}:{} is not co-installable with installed {}:{}.",
+        package.package, arch, ditem.package, darch
+      ));
+    }
+  }
+
+  Ok(())
 }
 
 pub fn search_dpkg_with_name_glob(glob: &Pattern, case_sensitive: bool) -> Vec<SourcePackage> {
@@ -149,164 +318,221 @@ pub fn search_dpkg_with_name_glob(glob: &Pattern, case_sensitive: bool) -> Vec<S
   ret_items
 }
 
-pub fn get_missing_or_old_dependencies(
-  package: &SourcePackage,
-  show_progress: bool,
-) -> Result<Vec<(String, PackageState)>, String> {
-  let mut ret_items = vec![];
+// unpack archives of @packages without configuring them.
+pub fn unpack_archived_packages(packages: &[SourcePackage]) -> Result<(), String> {
+  let mut args = vec!["--unpack".to_string()];
+  args.extend(packages.iter().map(fetcher::archive_path));
+  run_dpkg(&args, "Unpacking  ", packages.len(), &["unpacked"])
+}
 
-  for (dep_package, dep_version) in &package.depends {
-    let tmp = ProgressBar::new(0);
-    tmp.set_style(
-      ProgressStyle::default_bar().template(&format!("Check deps {}: {{msg}}", dep_package)),
-    );
-    let progress_bar = if show_progress { Some(&tmp) } else { None };
-
-    match check_missing_or_old(dep_package, dep_version, progress_bar) {
-      Ok(is_missing) => match is_missing {
-        PackageState::MISSING => ret_items.push((dep_package.clone(), PackageState::MISSING)),
-        PackageState::OLD => ret_items.push((dep_package.clone(), PackageState::OLD)),
-        _ => {}
-      },
-      Err(msg) => return Err(msg),
-    }
+// configure unpacked @packages.
+pub fn configure_packages(packages: &[SourcePackage]) -> Result<(), String> {
+  let mut args = vec!["--configure".to_string()];
+  args.extend(packages.iter().map(|p| p.arch_qualified_name()));
+  run_dpkg(
+    &args,
+    "Configuring",
+    packages.len(),
+    &["installed", "triggers-pending", "triggers-awaited"],
+  )
+}
+
+// run dpkg with @args, streaming its output to the terminal and DPKG_LOG.
+// progress bar counts packages which reach one of @done_states, reported via '--status-fd'.
+fn run_dpkg(
+  args: &[String],
+  title: &str,
+  total: usize,
+  done_states: &[&str],
+) -> Result<(), String> {
+  if let Err(msg) = fs::create_dir_all("log") {
+    return Err(format!("failed to create log directory: {}", msg));
   }
+  let log = match OpenOptions::new().create(true).append(true).open(DPKG_LOG) {
+    Ok(_log) => Arc::new(Mutex::new(_log)),
+    Err(msg) => return Err(format!("failed to open {}: {}", DPKG_LOG, msg)),
+  };
+  writeln!(log.lock().unwrap(), "\n$ dpkg {}", args.join(" ")).ok();
 
-  Ok(ret_items)
-}
+  // dpkg writes status into the write end of a pipe, which is inherited.
+  let mut fds = [0; 2];
+  if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+    return Err("failed to create a pipe for dpkg status.".to_string());
+  }
+  let (status_read, status_write) = (fds[0], fds[1]);
+  unsafe { libc::fcntl(status_read, libc::F_SETFD, libc::FD_CLOEXEC) };
+  let spawned = Command::new("dpkg")
+    .arg("--status-fd")
+    .arg(status_write.to_string())
+    .args(args)
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn();
+  // reading end gets EOF only after all writers are closed.
+  unsafe { libc::close(status_write) };
+  let status_reader = unsafe { File::from_raw_fd(status_read) };
+  let mut child = match spawned {
+    Ok(_child) => _child,
+    Err(msg) => return Err(format!("failed to execute dpkg: {}", msg)),
+  };
 
-fn sub_missing_or_old_dependencies_recursive(
-  package: &SourcePackage,
-  acc: &mut Vec<(String, PackageState)>,
-  show_progress: bool,
-) -> Result<Vec<(String, PackageState)>, String> {
-  // search missing/old dependencies for @package
-  let mut missing_package_names = get_missing_or_old_dependencies(package, show_progress)?;
-
-  // get instances of missing/old packages
-  let mut missing_packages = cache::search_cache_with_names(
-    &missing_package_names
-      .iter()
-      .map(|p| p.0.clone())
-      .collect::<Vec<_>>(),
+  let progress_bar = ProgressBar::new(total as u64);
+  progress_bar.set_style(
+    ProgressStyle::default_bar()
+      .template(&format!(
+        "{}: [{{bar:40.green}}] {{pos}}/{{len}} {{msg}}",
+        title
+      ))
+      .progress_chars("#>-"),
   );
+  let streamers = vec![
+    stream_output(child.stdout.take().unwrap(), false, &progress_bar, &log),
+    stream_output(child.stderr.take().unwrap(), true, &progress_bar, &log),
+  ];
 
-  // remove duplicated packages
-  missing_packages = missing_packages
-    .into_iter()
-    .filter(|item| {
-      acc
-        .iter()
-        .find(|a| a.0 == item.package || item.package == package.package)
-        .is_none()
-    })
-    .collect::<Vec<_>>();
-  missing_package_names = missing_package_names
-    .into_iter()
-    .filter(|name| {
-      acc
-        .iter()
-        .find(|a| a.0 == name.0 || name.0 == package.package)
-        .is_none()
-    })
-    .collect::<Vec<_>>();
-
-  // return error if some dependency is not in cache.
-  if missing_packages.len() < missing_package_names.len() {
-    let not_found_packages = missing_package_names
-      .iter()
-      .filter(|name| {
-        missing_packages
-          .iter()
-          .find(|mp| mp.package == name.0)
-          .is_none()
-      })
-      .collect::<Vec<_>>();
-    return Err(format!(
-      "Dependency packages not found in cache files: {}",
-      not_found_packages
-        .iter()
-        .map(|n| n.0.clone())
-        .collect::<Vec<_>>()
-        .join(", ")
-    ));
+  let mut done = vec![];
+  for line in BufReader::new(status_reader).lines() {
+    let line = match line {
+      Ok(_line) => _line,
+      Err(_) => break,
+    };
+    if let Some((package, state)) = parse_status_line(&line) {
+      progress_bar.set_message(package.clone());
+      if done_states.contains(&state.as_str()) && !done.contains(&package) {
+        done.push(package);
+        progress_bar.inc(1);
+      }
+    }
+  }
+  for streamer in streamers {
+    streamer.join().unwrap();
   }
 
-  acc.append(&mut missing_package_names);
-  // recursively search missing/old dependencies
-  for p in missing_packages {
-    match sub_missing_or_old_dependencies_recursive(&p, acc, show_progress) {
-      Ok(mut names) => {
-        names = names
-          .into_iter()
-          .filter(|item| acc.iter().find(|a| a.0 == item.0).is_none())
-          .collect::<Vec<_>>();
-        acc.append(&mut names);
-      }
-      Err(msg) => return Err(msg),
+  let status = match child.wait() {
+    Ok(_status) => _status,
+    Err(msg) => {
+      progress_bar.abandon();
+      return Err(format!("failed to wait for dpkg: {}", msg));
     }
+  };
+  if !status.success() {
+    progress_bar.abandon();
+    return Err(format!(
+      "dpkg exited with failing error code. see {} for details.",
+      DPKG_LOG
+    ));
   }
+  progress_bar.finish_with_message("DONE");
 
-  Ok(acc.to_vec())
+  Ok(())
 }
 
-pub fn get_missing_or_old_dependencies_recursive(
-  package: &SourcePackage,
-  _show_progress: bool,
-) -> Result<Vec<(String, PackageState)>, String> {
-  // recursive search
-  let (tx, rx) = mpsc::channel();
-  let progress_bar = ProgressBar::new(0);
-  progress_bar.set_style(
-    ProgressStyle::default_bar()
-      .template("{spinner:.blue} [{elapsed_precise}] {bar:70.blue} {msg}")
-      .progress_chars("+- "),
-  );
-  let _ = std::thread::spawn(move || {
-    let mut counter = 0;
-    loop {
-      if counter % 10 == 0 {
-        counter = 0;
-        progress_bar.inc_length(10);
-        progress_bar.inc(5);
-      }
-      thread::sleep(Duration::from_millis(20));
-      match rx.try_recv() {
-        Ok(_) | Err(TryRecvError::Disconnected) => {
-          progress_bar.finish_with_message("DONE");
-          break;
-        }
-        Err(TryRecvError::Empty) => {}
+// print lines from @output above @progress_bar as soon as they come, and keep them in @log.
+fn stream_output<R: Read + Send + 'static>(
+  output: R,
+  is_error: bool,
+  progress_bar: &ProgressBar,
+  log: &Arc<Mutex<File>>,
+) -> thread::JoinHandle<()> {
+  let (progress_bar, log) = (progress_bar.clone(), log.clone());
+  thread::spawn(move || {
+    for line in BufReader::new(output).lines() {
+      let line = match line {
+        Ok(_line) => _line,
+        Err(_) => break,
+      };
+      writeln!(log.lock().unwrap(), "{}", line).ok();
+      let line = if is_error {
+        line.red().to_string()
+      } else {
+        line
+      };
+      // hidden progress bar (not a terminal) prints nothing by itself.
+      if progress_bar.is_hidden() {
+        println!("{}", line);
+      } else {
+        progress_bar.println(line);
       }
-      counter += 1;
     }
-  });
-  let res = sub_missing_or_old_dependencies_recursive(package, &mut vec![], false);
-
-  tx.send(()).unwrap();
-  res
+  })
 }
 
-pub fn install_archived_package(package: &SourcePackage) -> Result<(), String> {
-  let _a = package.filename.rfind('/').unwrap();
-  let debname = format!("archive/{}", &package.filename[_a + 1..]);
-
-  let output = Command::new("dpkg")
-    .args(&["-i", &debname])
-    .stdout(Stdio::piped())
-    .stderr(Stdio::piped())
-    .spawn()
-    .unwrap()
-    .wait_with_output()
-    .unwrap();
-  let outstr = String::from_utf8(output.stdout).unwrap();
-  println!("{}", outstr);
+// @ret: (package, state) of 'status: <package>: <state>' reported by dpkg.
+//       errors('status: <package> : error : <message>') and others are ignored.
+pub fn parse_status_line(line: &str) -> Option<(String, String)> {
+  let status = line.strip_prefix("status: ")?;
+  if status.contains(" : ") {
+    return None;
+  }
+  let (package, state) = status.rsplit_once(": ")?;
+  Some((package.to_string(), state.to_string()))
+}
 
-  if !output.status.success() {
-    let errstr = String::from_utf8(output.stderr).unwrap();
-    println!("{}", errstr.red());
-    return Err("dpkg exited with failing error code.".to_string());
+#[cfg(test)]
+pub mod test {
+  #[test]
+  fn test_parse_status_line() {
+    use super::parse_status_line;
+    assert_eq!(
+      parse_status_line("status: libc6:amd64: unpacked"),
+      Some(("libc6:amd64".to_string(), "unpacked".to_string()))
+    );
+    assert_eq!(
+      parse_status_line("status: hello: half-configured"),
+      Some(("hello".to_string(), "half-configured".to_string()))
+    );
+    assert_eq!(
+      parse_status_line("status: hello : error : dependency problems"),
+      None
+    );
+    assert_eq!(parse_status_line("processing: unpack: hello"), None);
   }
 
-  Ok(())
+  #[test]
+  fn test_check_version() {
+    use super::{check_version, PackageState};
+    use crate::source::RelOp;
+    let rel = |op, v: &str| Some((op, v.to_string()));
+    assert_eq!(check_version("1.0-1", &None), PackageState::UPTODATE);
+    assert_eq!(
+      check_version("1.0-1", &rel(RelOp::GE, "1.0")),
+      PackageState::UPTODATE
+    );
+    assert_eq!(
+      check_version("1.0-1", &rel(RelOp::GE, "1.1")),
+      PackageState::OLD
+    );
+    assert_eq!(
+      check_version("1.0-1", &rel(RelOp::GT, "1.0-1")),
+      PackageState::OLD
+    );
+    assert_eq!(
+      check_version("1.0-1", &rel(RelOp::EQ, "1.0-1")),
+      PackageState::UPTODATE
+    );
+    assert_eq!(
+      check_version("1.0-1", &rel(RelOp::EQ, "1.0-2")),
+      PackageState::OLD
+    );
+    assert_eq!(
+      check_version("1.0-3", &rel(RelOp::EQ, "1.0-2")),
+      PackageState::CONFLICT
+    );
+    assert_eq!(
+      check_version("2.0", &rel(RelOp::LT, "2.0")),
+      PackageState::CONFLICT
+    );
+    assert_eq!(
+      check_version("1.9", &rel(RelOp::LT, "2.0")),
+      PackageState::UPTODATE
+    );
+    assert_eq!(
+      check_version("2.0", &rel(RelOp::LE, "2.0")),
+      PackageState::UPTODATE
+    );
+    assert_eq!(
+      check_version("2:0.1", &rel(RelOp::LE, "2.0")),
+      PackageState::CONFLICT
+    );
+  }
 }
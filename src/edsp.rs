@@ -0,0 +1,378 @@
+use crate::config::CONFIG;
+use crate::dpkg::{self, DependencyFlags};
+use crate::resolver::{Constraints, Solution};
+use crate::source::{self, MultiArch, SourcePackage, StatusStatus};
+use std::collections::HashMap;
+
+// External Dependency Solver Protocol, spoken by apt's solvers and aspcud.
+// (https://salsa.debian.org/apt-team/apt/-/blob/main/doc/external-dependency-solver-protocol.md)
+pub const EDSP_VERSION: &str = "EDSP 0.5";
+
+// how dependencies of packages to be installed are resolved.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum Solver {
+  // resolver::resolve()
+  #[default]
+  INTERNAL,
+  // path of a solver binary, which reads a scenario from stdin and answers to stdout.
+  EXTERNAL(String),
+  // just write the scenario into the file, without solving it.
+  DUMP(String),
+}
+
+// a package in the scenario, whose APT-ID is its index in the universe.
+struct Entry<'a> {
+  package: &'a SourcePackage,
+  installed: bool,
+  candidate: bool,
+}
+
+// @ret: installed packages and candidates in @cache. the same version is merged.
+fn universe<'a>(installed: &'a [SourcePackage], cache: &'a [SourcePackage]) -> Vec<Entry<'a>> {
+  let mut entries = installed
+    .iter()
+    .filter(|i| i.status.status == StatusStatus::INSTALLED)
+    .map(|i| Entry {
+      package: i,
+      installed: true,
+      candidate: false,
+    })
+    .collect::<Vec<_>>();
+  let installed_versions = entries
+    .iter()
+    .enumerate()
+    .map(|(ix, e)| {
+      (
+        (e.package.arch_qualified_name(), e.package.version.clone()),
+        ix,
+      )
+    })
+    .collect::<HashMap<_, _>>();
+  for item in cache {
+    match installed_versions.get(&(item.arch_qualified_name(), item.version.clone())) {
+      Some(ix) => entries[*ix].candidate = true,
+      None => entries.push(Entry {
+        package: item,
+        installed: false,
+        candidate: true,
+      }),
+    }
+  }
+  entries
+}
+
+// @ret: EDSP scenario which asks to install @requests.
+pub fn write_scenario(
+  requests: &[SourcePackage],
+  installed: &[SourcePackage],
+  cache: &[SourcePackage],
+  flags: DependencyFlags,
+) -> String {
+  let action = format!(
+    "Install: {}\n",
+    requests
+      .iter()
+      .map(|r| r.arch_qualified_name())
+      .collect::<Vec<_>>()
+      .join(" ")
+  );
+  write_scenario_with(&action, installed, cache, flags)
+}
+
+// @ret: EDSP scenario which asks to upgrade installed packages as many as possible
+//       within @constraints.
+pub fn write_upgrade_scenario(
+  installed: &[SourcePackage],
+  cache: &[SourcePackage],
+  flags: DependencyFlags,
+  constraints: Constraints,
+) -> String {
+  let mut action = "Upgrade-All: yes\n".to_string();
+  if constraints.forbid_new_install {
+    action += "Forbid-New-Install: yes\n";
+  }
+  if constraints.forbid_remove {
+    action += "Forbid-Remove: yes\n";
+  }
+  write_scenario_with(&action, installed, cache, flags)
+}
+
+// @action: fields of the request stanza which tell what to do.
+fn write_scenario_with(
+  action: &str,
+  installed: &[SourcePackage],
+  cache: &[SourcePackage],
+  flags: DependencyFlags,
+) -> String {
+  let mut scenario = String::new();
+  scenario += &format!("Request: {}\n", EDSP_VERSION);
+  scenario += "Solver: rapt\n";
+  scenario += &format!("Architecture: {}\n", CONFIG.architecture);
+  scenario += &format!("Architectures: {}\n", CONFIG.architectures().join(" "));
+  scenario += action;
+  // missing fields mean yes for recommends and no for suggests, so both are explicit.
+  let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+  scenario += &format!("Install-Recommends: {}\n", yes_no(flags.recommends));
+  scenario += &format!("Install-Suggests: {}\n", yes_no(flags.suggests));
+  scenario += "\n";
+
+  for (id, entry) in universe(installed, cache).iter().enumerate() {
+    let package = entry.package;
+    scenario += &format!("Package: {}\n", package.package);
+    scenario += &format!("Architecture: {}\n", package.arch_name());
+    scenario += &format!("Version: {}\n", package.version);
+    scenario += &format!("APT-ID: {}\n", id);
+    scenario += &format!("APT-Pin: {}\n", if entry.candidate { 500 } else { 100 });
+    if entry.candidate {
+      scenario += "APT-Candidate: yes\n";
+    }
+    if entry.installed {
+      scenario += "Installed: yes\n";
+      if package.is_held() {
+        scenario += "Hold: yes\n";
+      }
+    }
+    if package.essential {
+      scenario += "Essential: yes\n";
+    }
+    if package.multi_arch != MultiArch::NO {
+      scenario += &format!(
+        "Multi-Arch: {}\n",
+        package.multi_arch.to_string().to_lowercase()
+      );
+    }
+    let fields = [
+      ("Pre-Depends", &package.pre_depends),
+      ("Depends", &package.depends),
+      ("Recommends", &package.recommends),
+      ("Suggests", &package.suggests),
+      ("Conflicts", &package.conflicts),
+      ("Breaks", &package.breaks),
+      ("Replaces", &package.replaces),
+    ];
+    for (field, groups) in fields {
+      if !groups.is_empty() {
+        scenario += &format!("{}: {}\n", field, source::format_depends(groups));
+      }
+    }
+    if !package.provides.is_empty() {
+      let provides = package
+        .provides
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>();
+      scenario += &format!("Provides: {}\n", provides.join(", "));
+    }
+    scenario += "\n";
+  }
+
+  scenario
+}
+
+// @ret: changes decided in EDSP @answer, whose APT-IDs point to the universe of the scenario.
+pub fn read_answer(
+  answer: &str,
+  installed: &[SourcePackage],
+  cache: &[SourcePackage],
+) -> Result<Solution, String> {
+  let entries = universe(installed, cache);
+  let entry = |id: &str| match id.trim().parse::<usize>() {
+    Ok(ix) if ix < entries.len() => Ok(&entries[ix]),
+    _ => Err(format!("external solver answered unknown APT-ID: {}", id)),
+  };
+
+  let mut solution = Solution::default();
+  for stanza in answer.split("\n\n") {
+    let mut fields = HashMap::new();
+    let mut last_key = "";
+    for line in stanza.lines() {
+      // continuation line of multiline field
+      if line.starts_with(' ') || line.starts_with('\t') {
+        if let Some(value) = fields.get_mut(last_key) {
+          *value = format!("{}\n{}", value, line.trim());
+        }
+        continue;
+      }
+      let kv = line.splitn(2, ':').collect::<Vec<_>>();
+      if kv.len() == 2 {
+        last_key = kv[0];
+        fields.insert(kv[0], kv[1].trim().to_string());
+      }
+    }
+
+    if let Some(id) = fields.get("Install") {
+      let entry = entry(id)?;
+      if entry.installed {
+        continue;
+      }
+      let package = entry.package.clone();
+      let upgraded = installed.iter().any(|i| {
+        i.status.status == StatusStatus::INSTALLED
+          && i.arch_qualified_name() == package.arch_qualified_name()
+      });
+      if upgraded {
+        solution.upgrades.push(package);
+      } else {
+        solution.installs.push(package);
+      }
+    } else if let Some(id) = fields.get("Remove") {
+      let entry = entry(id)?;
+      // removal of old version comes with installation of the new one.
+      let upgraded = solution
+        .upgrades
+        .iter()
+        .any(|u| u.arch_qualified_name() == entry.package.arch_qualified_name());
+      if entry.installed && !upgraded {
+        solution.removals.push(entry.package.clone());
+      }
+    } else if fields.contains_key("Error") {
+      return Err(format!(
+        "external solver failed: {}",
+        fields.get("Message").cloned().unwrap_or_default()
+      ));
+    } else if let Some(message) = fields.get("Progress").and(fields.get("Message")) {
+      log::info!("external solver: {}", message);
+    }
+  }
+  // an upgrade may be answered after the removal of the old version.
+  let upgrades = &solution.upgrades;
+  solution.removals.retain(|r| {
+    !upgrades
+      .iter()
+      .any(|u| u.arch_qualified_name() == r.arch_qualified_name())
+  });
+
+  Ok(solution)
+}
+
+// solve the scenario to install @requests by external @solver.
+pub fn solve(
+  solver: &str,
+  requests: &[SourcePackage],
+  installed: &[SourcePackage],
+  cache: &[SourcePackage],
+  flags: DependencyFlags,
+) -> Result<Solution, String> {
+  let scenario = write_scenario(requests, installed, cache, flags);
+  run_solver(solver, scenario, installed, cache)
+}
+
+// solve the scenario to upgrade installed packages within @constraints by external @solver.
+pub fn upgrade(
+  solver: &str,
+  installed: &[SourcePackage],
+  cache: &[SourcePackage],
+  flags: DependencyFlags,
+  constraints: Constraints,
+) -> Result<Solution, String> {
+  let scenario = write_upgrade_scenario(installed, cache, flags, constraints);
+  run_solver(solver, scenario, installed, cache)
+}
+
+fn run_solver(
+  solver: &str,
+  scenario: String,
+  installed: &[SourcePackage],
+  cache: &[SourcePackage],
+) -> Result<Solution, String> {
+  let output = dpkg::run_with_input(solver, &[], scenario)?;
+
+  if !output.status.success() {
+    let errstr = String::from_utf8_lossy(&output.stderr);
+    return Err(format!(
+      "external solver {} exited with failing error code:\n{}",
+      solver,
+      errstr.trim_end()
+    ));
+  }
+
+  read_answer(&String::from_utf8_lossy(&output.stdout), installed, cache)
+}
+
+// write the scenario to install @requests into @path.
+pub fn dump(
+  path: &str,
+  requests: &[SourcePackage],
+  installed: &[SourcePackage],
+  cache: &[SourcePackage],
+  flags: DependencyFlags,
+) -> Result<(), String> {
+  match std::fs::write(path, write_scenario(requests, installed, cache, flags)) {
+    Ok(()) => Ok(()),
+    Err(msg) => Err(format!("failed to write {}: {}", path, msg)),
+  }
+}
+
+#[cfg(test)]
+pub mod test {
+  use crate::source::SourcePackage;
+
+  #[test]
+  fn test_edsp() {
+    let parse = |raw: &str| SourcePackage::from_raw(raw, "").unwrap();
+    let installed = parse(
+      "Package: oldlib\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1.0\n\n\
+       Package: mta\nStatus: install ok installed\nArchitecture: amd64\nVersion: 2.0\n\
+       Provides: mail-transport-agent\n\n\
+       Package: gone\nStatus: deinstall ok config-files\nArchitecture: amd64\nVersion: 1\n\n",
+    );
+    let cache = parse(
+      "Package: oldlib\nArchitecture: amd64\nVersion: 1.1\n\n\
+       Package: mta\nArchitecture: amd64\nVersion: 2.0\nProvides: mail-transport-agent\n\n\
+       Package: app\nArchitecture: amd64\nVersion: 1\nDepends: oldlib (>= 1.1)\n\
+       Conflicts: mail-transport-agent\nMulti-Arch: foreign\nSuggests: mta\n\n",
+    );
+    let scenario = super::write_scenario(&cache[2..3], &installed, &cache, Default::default());
+    let stanzas = scenario.split("\n\n").collect::<Vec<_>>();
+    assert!(stanzas[0].starts_with("Request: EDSP 0.5\n"));
+    assert!(stanzas[0].contains("Install: app:amd64\n"));
+    assert!(stanzas[0].contains("Install-Recommends: yes\n"));
+    assert!(stanzas[0].contains("Install-Suggests: no"));
+    // installed version which is also the candidate is merged.
+    assert!(stanzas[1].contains("Package: oldlib\n") && stanzas[1].contains("APT-Pin: 100\n"));
+    assert!(stanzas[2].contains("APT-ID: 1\n") && stanzas[2].contains("APT-Candidate: yes\n"));
+    assert!(stanzas[2].contains("Installed: yes\n"));
+    assert!(stanzas[3].contains("Version: 1.1\n") && !stanzas[3].contains("Installed"));
+    assert!(stanzas[4].contains("Depends: oldlib (>= 1.1)\n"));
+    assert!(stanzas[4].contains("Multi-Arch: foreign\n"));
+    assert!(stanzas[4].contains("Suggests: mta\n"));
+    assert!(!scenario.contains("Package: gone"));
+
+    // '--no-install-recommends' reaches the solver.
+    let flags = crate::dpkg::DependencyFlags {
+      recommends: false,
+      suggests: true,
+    };
+    let scenario = super::write_scenario(&cache[2..3], &installed, &cache, flags);
+    let request = scenario.split("\n\n").next().unwrap();
+    assert!(request.contains("Install-Recommends: no\n"));
+    assert!(request.contains("Install-Suggests: yes"));
+
+    let constraints = crate::resolver::Constraints {
+      forbid_new_install: true,
+      forbid_remove: true,
+    };
+    let scenario = super::write_upgrade_scenario(&installed, &cache, flags, constraints);
+    let request = scenario.split("\n\n").next().unwrap();
+    assert!(request.contains("Upgrade-All: yes\nForbid-New-Install: yes\nForbid-Remove: yes\n"));
+    assert!(!request.contains("\nInstall:"));
+
+    let answer = "Progress: 2021-01-01 00:00:00\nPercentage: 50\nMessage: solving\n\n\
+                  Install: 3\nPackage: app\n\n\
+                  Remove: 0\n\n\
+                  Install: 2\n\n\
+                  Remove: 1\n\n";
+    let solution = super::read_answer(answer, &installed, &cache).unwrap();
+    assert_eq!(solution.installs[0].package, "app");
+    assert_eq!(solution.upgrades[0].version, "1.1");
+    assert_eq!(solution.removals.len(), 1);
+    assert_eq!(solution.removals[0].package, "mta");
+
+    let answer = "Error: rapt\nMessage: no solution\n found.\n\n";
+    assert_eq!(
+      super::read_answer(answer, &installed, &cache).unwrap_err(),
+      "external solver failed: no solution\nfound."
+    );
+    assert!(super::read_answer("Install: 99\n\n", &installed, &cache).is_err());
+  }
+}
@@ -1,163 +1,270 @@
-use crate::{slist, source};
+use crate::release::Release;
+use crate::{chksum, gpgv, slist, source, transport};
 use flate2::read::GzDecoder;
 use indicatif::ProgressBar;
-use reqwest::{header, Client};
 use std::io::prelude::*;
 
+// @ret: (path of the deb file, fetched size, whether cached archive is used)
 pub fn fetch_deb(
   package: &source::SourcePackage,
   _progress_bar: Option<&ProgressBar>,
-) -> Result<(String, u32), String> {
+) -> Result<(String, u64, bool), String> {
   // create archive directory
   if !std::path::Path::new("archive").exists() {
     std::fs::create_dir("archive").unwrap();
   }
 
-  /* XXX must check archive directory first for cache HERE */
+  // reuse already downloaded archive if it is the very one in the index.
+  if let Some(debpath) = find_cached_deb(package) {
+    if let Some(progress_bar) = _progress_bar {
+      progress_bar.set_message(format!("{} (cached)", debpath));
+      progress_bar.set_length(package.size);
+      progress_bar.set_position(package.size);
+      progress_bar.finish();
+    }
+    return Ok((debpath, 0, true));
+  }
 
-  let uri = match package.to_pool_uri() {
-    Ok(_uri) => _uri,
-    Err(()) => {
-      return Err(format!(
-        "failed to open cache or package not found in caches: {}",
-        package.package
-      ))
+  let uri = package.to_pool_uri()?;
+  let transport = transport::get_transport(&uri)?;
+
+  // archives of file:// sources are used in place.
+  if let Some(localpath) = transport.local_path(&uri) {
+    let hasher = chksum::hash_file(&localpath)?;
+    if let Err(msg) = verify_archive(package, &hasher) {
+      return Err(format!("{}: {}", localpath, msg));
     }
-  };
+    if let Some(progress_bar) = _progress_bar {
+      progress_bar.set_message(format!("{} (local)", localpath));
+      progress_bar.set_length(package.size);
+      progress_bar.set_position(package.size);
+      progress_bar.finish();
+    }
+    // neither fetched nor taken from archive/.
+    return Ok((localpath, 0, false));
+  }
 
   let _a = uri.rfind('/').unwrap();
   let debname = String::from(&uri[_a + 1..]);
-  let mut output = std::fs::File::create(format!("archive/{}", &debname)).unwrap();
-  match _progress_bar {
-    Some(progress_bar) => {
-      let task = async {
-        let client = Client::new();
-        let download_size = {
-          let res = client.head(uri.clone()).send().await.unwrap();
-          if res.status().is_success() {
-            res
-              .headers()
-              .get(header::CONTENT_LENGTH)
-              .and_then(|ct_len| ct_len.to_str().ok())
-              .and_then(|ct_len| ct_len.parse().ok())
-              .unwrap_or(0)
-          } else {
-            return Err("unknown error while deb.".to_string());
-          }
-        };
-        progress_bar.set_message(uri.clone());
-        progress_bar.set_length(download_size);
-        progress_bar.set_position(0);
-
-        // actual request
-        let req = client.get(uri.clone());
-        let mut download = req.send().await.unwrap();
-        let mut content: Vec<u8> = vec![];
-        while let Some(chunk) = download.chunk().await.unwrap() {
-          progress_bar.inc(chunk.len() as u64);
-          tokio::io::AsyncWriteExt::write(&mut content, &chunk)
-            .await
-            .unwrap();
-        }
-        output.write_all(&content).unwrap();
-
-        progress_bar.finish();
-        Ok(())
-      };
+  let debpath = format!("archive/{}", &debname);
+  let partial_path = format!("archive/partial/{}", &debname);
+  if let Err(msg) = std::fs::create_dir_all("archive/partial") {
+    return Err(format!("failed to create archive/partial: {}", msg));
+  }
 
-      let rt = tokio::runtime::Runtime::new().unwrap();
-      rt.block_on(task).unwrap();
+  // resume from partial file left by the interrupted download.
+  let mut hasher = if std::path::Path::new(&partial_path).exists() {
+    chksum::hash_file(&partial_path)?
+  } else {
+    chksum::Hasher::new()
+  };
+  let mut fetched_size = 0;
+  if package.size == 0 || hasher.size() < package.size {
+    fetched_size = transport.fetch_to_file(&uri, &partial_path, &mut hasher, _progress_bar)?;
+  }
+
+  // never leave broken or tampered archive for dpkg.
+  if let Err(msg) = verify_archive(package, &hasher) {
+    std::fs::remove_file(&partial_path).ok();
+    return Err(format!("{}: {}", debname, msg));
+  }
+  if let Err(msg) = std::fs::rename(&partial_path, &debpath) {
+    return Err(format!("failed to move {} into archive: {}", debname, msg));
+  }
+
+  Ok((debpath, fetched_size, false))
+}
 
-      Ok((debname, 0))
+// @ret: path of the deb file to be installed for @package.
+// archives of file:// sources are not copied into archive/.
+pub fn archive_path(package: &source::SourcePackage) -> String {
+  if let Ok(uri) = package.to_pool_uri() {
+    if let Ok(transport) = transport::get_transport(&uri) {
+      if let Some(localpath) = transport.local_path(&uri) {
+        return localpath;
+      }
     }
-    None => {
-      let res = reqwest::blocking::get(&uri).expect("unknown error while fetching package.");
-      if !res.status().is_success() {
-        return Err(format!(
-          "error while fetching index: error code={}",
-          res.status().as_str()
-        ));
+  }
+  let debname = match package.filename.rfind('/') {
+    Some(_a) => &package.filename[_a + 1..],
+    None => &package.filename[..],
+  };
+  format!("archive/{}", debname)
+}
+
+// @ret: path of the deb file in archive/ which is valid for @package.
+pub fn find_cached_deb(package: &source::SourcePackage) -> Option<String> {
+  let debname = match package.filename.rfind('/') {
+    Some(_a) => String::from(&package.filename[_a + 1..]),
+    None => package.filename.clone(),
+  };
+  let debpath = format!("archive/{}", debname);
+  if debname.is_empty() || !std::path::Path::new(&debpath).exists() {
+    return None;
+  }
+  match chksum::hash_file(&debpath) {
+    Ok(hasher) => match verify_archive(package, &hasher) {
+      Ok(()) => Some(debpath),
+      Err(msg) => {
+        log::info!("ignoring cached archive {}: {}", debpath, msg);
+        None
       }
-      let content = res.bytes().unwrap();
-      output.write_all(&content).unwrap();
+    },
+    Err(_) => None,
+  }
+}
 
-      Ok((debname, 0))
+// check the archive against Size, SHA256, SHA1 and MD5sum of the index.
+pub fn verify_archive(
+  package: &source::SourcePackage,
+  hasher: &chksum::Hasher,
+) -> Result<(), String> {
+  if package.size != 0 && package.size != hasher.size() {
+    return Err(format!(
+      "size mismatch: expected {}, actual {}",
+      package.size,
+      hasher.size()
+    ));
+  }
+
+  let expected_hashes = vec![
+    (chksum::HashKind::SHA256, &package.chksum_sha256),
+    (chksum::HashKind::SHA1, &package.chksum_sha1),
+    (chksum::HashKind::MD5, &package.chksum_md5),
+  ];
+  let mut checked = false;
+  for (kind, expected) in expected_hashes {
+    if expected.is_empty() {
+      continue;
+    }
+    let actual = hasher.hex(kind);
+    if actual != expected.to_lowercase() {
+      return Err(format!(
+        "{} mismatch: expected {}, actual {}",
+        kind, expected, actual
+      ));
     }
+    checked = true;
+  }
+  if !checked {
+    return Err("no checksum found in the index.".to_string());
   }
+
+  Ok(())
 }
 
-pub fn fetch_index(
-  source: &slist::Source,
-  _progress_bar: Option<ProgressBar>,
-) -> Result<String, String> {
-  let mut buf: Vec<u8> = vec![];
-  let indexuri = source.to_index_uri();
-  match _progress_bar {
-    Some(progress_bar) => {
-      let _indexuri = indexuri.clone();
-      let task = async {
-        let client = Client::new();
-        let download_size = {
-          let res = client.head(_indexuri.clone()).send().await.unwrap();
-          if res.status().is_success() {
-            res
-              .headers()
-              .get(header::CONTENT_LENGTH)
-              .and_then(|ct_len| ct_len.to_str().ok())
-              .and_then(|ct_len| ct_len.parse().ok())
-              .unwrap_or(0)
-          } else {
-            return Err("unknown error while fetching index.".to_string());
-          }
-        };
-
-        progress_bar.set_message(_indexuri.clone());
-        progress_bar.set_length(download_size);
-        progress_bar.set_position(0);
-
-        // actual request
-        let req = client.get(_indexuri.clone());
-        let mut download = req.send().await.unwrap();
-        while let Some(chunk) = download.chunk().await.unwrap() {
-          progress_bar.inc(chunk.len() as u64);
-          tokio::io::AsyncWriteExt::write(&mut buf, &chunk)
-            .await
-            .unwrap();
-        }
-
-        progress_bar.finish();
-        Ok(())
-      };
+// @ret: None if the file doesn't exist in the repository.
+pub fn fetch_raw(uri: &str) -> Result<Option<Vec<u8>>, String> {
+  transport::get_transport(uri)?.fetch(uri, None)
+}
 
-      let rt = tokio::runtime::Runtime::new().unwrap();
-      rt.block_on(task).unwrap();
+// fetch 'InRelease' (or 'Release' and 'Release.gpg') of @source,
+// and return the parsed Release file only if its signature is valid.
+// signature is not checked for 'trusted=yes' source, and None is returned
+// if such a source doesn't have Release file at all.
+pub fn fetch_release(source: &slist::Source, trusted_dir: &str) -> Result<Option<Release>, String> {
+  let raw_release = match fetch_release_raw(source, trusted_dir)? {
+    Some(_raw_release) => _raw_release,
+    None => return Ok(None),
+  };
+  let release = match Release::from_raw(&raw_release) {
+    Ok(_release) => _release,
+    Err(msg) => return Err(format!("{}: {}", source.info(), msg)),
+  };
+  if source.check_valid_until {
+    let now = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .unwrap()
+      .as_secs();
+    if let Err(msg) = release.check_valid_until(now) {
+      return Err(format!("{}: {}", source.info(), msg));
+    }
+  }
 
-      let mut d = GzDecoder::new(&buf[..]);
-      let mut s = String::new();
-      d.read_to_string(&mut s).unwrap();
+  Ok(Some(release))
+}
 
-      Ok(s)
+fn fetch_release_raw(source: &slist::Source, trusted_dir: &str) -> Result<Option<String>, String> {
+  if source.trusted {
+    log::warn!(
+      "skipping signature check of trusted source: {}",
+      source.info()
+    );
+    if let Some(inrelease) = fetch_raw(&source.to_release_uri("InRelease"))? {
+      return match gpgv::strip_inline(&inrelease) {
+        Ok(release) => Ok(Some(release)),
+        Err(msg) => Err(format!("{}: InRelease: {}", source.info(), msg)),
+      };
     }
+    return match fetch_raw(&source.to_release_uri("Release"))? {
+      Some(release) => match String::from_utf8(release) {
+        Ok(_release) => Ok(Some(_release)),
+        Err(_) => Err(format!("{}: Release is not valid UTF-8.", source.info())),
+      },
+      None => Ok(None),
+    };
+  }
+
+  let keyrings = gpgv::collect_keyrings(trusted_dir, &source.signed_by)?;
+
+  if let Some(inrelease) = fetch_raw(&source.to_release_uri("InRelease"))? {
+    return match gpgv::verify_inline(&inrelease, &keyrings) {
+      Ok(release) => Ok(Some(release)),
+      Err(msg) => Err(format!("{}: InRelease: {}", source.info(), msg)),
+    };
+  }
+
+  let release = match fetch_raw(&source.to_release_uri("Release"))? {
+    Some(_release) => _release,
     None => {
-      let mut res = reqwest::blocking::get(indexuri).expect("unknown error while fetching index.");
-      if !res.status().is_success() {
-        return Err(format!(
-          "error while fetching index: error code={}",
-          res.status().as_str()
-        ));
-      }
-      let mut buf: Vec<u8> = vec![];
-      res
-        .copy_to(&mut buf)
-        .expect("error while copying result into buffer.");
+      return Err(format!(
+        "{}: neither InRelease nor Release found.",
+        source.info()
+      ))
+    }
+  };
+  let signature = match fetch_raw(&source.to_release_uri("Release.gpg"))? {
+    Some(_signature) => _signature,
+    None => return Err(format!("{}: Release is not signed.", source.info())),
+  };
+  if let Err(msg) = gpgv::verify_detached(&release, &signature, &keyrings) {
+    return Err(format!("{}: Release: {}", source.info(), msg));
+  }
+  match String::from_utf8(release) {
+    Ok(_release) => Ok(Some(_release)),
+    Err(_) => Err(format!("{}: Release is not valid UTF-8.", source.info())),
+  }
+}
 
-      let mut d = GzDecoder::new(&buf[..]);
-      let mut s = String::new();
-      d.read_to_string(&mut s).unwrap();
+pub fn fetch_index(
+  source: &slist::Source,
+  arch: &str,
+  release: Option<&Release>,
+  _progress_bar: Option<ProgressBar>,
+) -> Result<String, String> {
+  let indexuri = source.to_index_uri(arch);
+  let buf = match transport::get_transport(&indexuri)?.fetch(&indexuri, _progress_bar.as_ref())? {
+    Some(_buf) => _buf,
+    None => {
+      return Err(format!(
+        "error while fetching index: {} not found.",
+        indexuri
+      ))
+    }
+  };
 
-      Ok(s)
+  if let Some(release) = release {
+    if let Err(msg) = release.verify_file(&source.to_index_path(arch), &buf) {
+      return Err(format!("{}: {}", source.info(), msg));
     }
   }
+  let mut d = GzDecoder::new(&buf[..]);
+  let mut s = String::new();
+  if let Err(msg) = d.read_to_string(&mut s) {
+    return Err(format!("failed to decompress {}: {}", indexuri, msg));
+  }
+
+  Ok(s)
 }
 
 #[cfg(test)]
@@ -168,7 +275,11 @@ pub mod test {
     let source =
       &slist::parse_source_line("deb http://jp.archive.ubuntu.com/ubuntu/ focal main restricted")
         .unwrap()[0];
-    println!("{}", super::fetch_index(&source, None).unwrap());
+    let release = super::fetch_release(source, crate::gpgv::DEFAULT_TRUSTED_DIR).unwrap();
+    println!(
+      "{}",
+      super::fetch_index(source, "amd64", release.as_ref(), None).unwrap()
+    );
   }
 
   #[allow(dead_code)]
@@ -180,4 +291,42 @@ pub mod test {
     };
     super::fetch_deb(&p, None).unwrap();
   }
+
+  #[test]
+  fn test_verify_archive() {
+    use crate::chksum::Hasher;
+    use crate::source::SourcePackage;
+    let data = std::fs::read("test/sample-index").unwrap();
+    let mut hasher = Hasher::new();
+    hasher.update(&data);
+    let p = SourcePackage {
+      package: "dpkg".to_string(),
+      size: data.len() as u64,
+      chksum_md5: crate::chksum::hash_hex(crate::chksum::HashKind::MD5, &data),
+      chksum_sha256: crate::chksum::hash_hex(crate::chksum::HashKind::SHA256, &data),
+      ..Default::default()
+    };
+    assert!(super::verify_archive(&p, &hasher).is_ok());
+
+    let wrong_size = SourcePackage {
+      size: 1,
+      ..p.clone()
+    };
+    assert!(super::verify_archive(&wrong_size, &hasher)
+      .unwrap_err()
+      .contains("size mismatch"));
+    let wrong_sha256 = SourcePackage {
+      chksum_sha256: "00".repeat(32),
+      ..p.clone()
+    };
+    assert!(super::verify_archive(&wrong_sha256, &hasher)
+      .unwrap_err()
+      .contains("SHA256 mismatch"));
+    let no_hash = SourcePackage {
+      chksum_md5: "".to_string(),
+      chksum_sha256: "".to_string(),
+      ..p.clone()
+    };
+    assert!(super::verify_archive(&no_hash, &hasher).is_err());
+  }
 }
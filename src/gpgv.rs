@@ -0,0 +1,217 @@
+use std::io::prelude::*;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const DEFAULT_TRUSTED_DIR: &str = "trusted.gpg.d";
+
+static SIGFILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// collect keyrings used to verify a source.
+// if the source has 'signed-by', only that keyring is trusted (same as apt).
+// otherwise, every binary keyring(*.gpg) in @trusted_dir is trusted.
+pub fn collect_keyrings(
+  trusted_dir: &str,
+  signed_by: &Option<String>,
+) -> Result<Vec<String>, String> {
+  let mut keyrings = vec![];
+  match signed_by {
+    Some(keyring) => keyrings.push(keyring.clone()),
+    None => match glob::glob(&format!("{}/*.gpg", trusted_dir)) {
+      Ok(paths) => {
+        for entry in paths {
+          match entry {
+            Ok(path) => keyrings.push(path.to_str().unwrap().to_string()),
+            Err(msg) => return Err(format!("failed to open keyring: {}", msg)),
+          }
+        }
+      }
+      Err(_) => return Err(format!("invalid trusted directory: {}", trusted_dir)),
+    },
+  };
+
+  // gpgv interprets relative keyring path as relative to its home directory.
+  let mut abs_keyrings = vec![];
+  for keyring in keyrings {
+    match std::fs::canonicalize(&keyring) {
+      Ok(path) => abs_keyrings.push(path.to_str().unwrap().to_string()),
+      Err(_) => return Err(format!("keyring not found: {}", keyring)),
+    }
+  }
+  if abs_keyrings.is_empty() {
+    return Err(format!("no trusted keyring found in {}", trusted_dir));
+  }
+
+  Ok(abs_keyrings)
+}
+
+// verify clearsigned file such as 'InRelease', and return its signed content.
+pub fn verify_inline(signed: &[u8], keyrings: &[String]) -> Result<String, String> {
+  let mut args = keyring_args(keyrings)?;
+  args.append(&mut vec![
+    "--output".to_string(),
+    "-".to_string(),
+    "-".to_string(),
+  ]);
+  let output = run_gpgv(&args, signed)?;
+
+  match String::from_utf8(output) {
+    Ok(content) => Ok(content),
+    Err(_) => Err("signed content is not valid UTF-8.".to_string()),
+  }
+}
+
+// verify detached signature such as 'Release.gpg' against @data.
+pub fn verify_detached(data: &[u8], signature: &[u8], keyrings: &[String]) -> Result<(), String> {
+  let mut args = keyring_args(keyrings)?;
+  let sigpath = std::env::temp_dir().join(format!(
+    "rapt-{}-{}.gpg",
+    std::process::id(),
+    SIGFILE_COUNTER.fetch_add(1, Ordering::SeqCst)
+  ));
+  if let Err(msg) = std::fs::write(&sigpath, signature) {
+    return Err(format!("failed to write signature file: {}", msg));
+  }
+
+  args.push(sigpath.to_str().unwrap().to_string());
+  args.push("-".to_string());
+  let res = run_gpgv(&args, data);
+  std::fs::remove_file(&sigpath).ok();
+
+  res.map(|_| ())
+}
+
+// extract signed content of clearsigned file WITHOUT verification.
+// this must be used only for sources explicitly marked as trusted.
+pub fn strip_inline(signed: &[u8]) -> Result<String, String> {
+  let signed = match std::str::from_utf8(signed) {
+    Ok(_signed) => _signed,
+    Err(_) => return Err("signed content is not valid UTF-8.".to_string()),
+  };
+  let mut lines = signed.lines();
+  if lines.next() != Some("-----BEGIN PGP SIGNED MESSAGE-----") {
+    return Err("not a clearsigned message.".to_string());
+  }
+  // skip armor headers such as 'Hash: SHA512'
+  for line in &mut lines {
+    if line.is_empty() {
+      break;
+    }
+  }
+
+  let mut content = String::new();
+  for line in lines {
+    if line == "-----BEGIN PGP SIGNATURE-----" {
+      return Ok(content);
+    }
+    // dash-escaped line
+    content.push_str(line.strip_prefix("- ").unwrap_or(line));
+    content.push('\n');
+  }
+
+  Err("signature block is missing.".to_string())
+}
+
+fn keyring_args(keyrings: &[String]) -> Result<Vec<String>, String> {
+  // never let gpgv fall back to the user's default keyring.
+  if keyrings.is_empty() {
+    return Err("no keyring is specified.".to_string());
+  }
+  let mut args = vec![];
+  for keyring in keyrings {
+    args.push("--keyring".to_string());
+    args.push(keyring.clone());
+  }
+  Ok(args)
+}
+
+fn run_gpgv(args: &[String], input: &[u8]) -> Result<Vec<u8>, String> {
+  let mut child = match Command::new("gpgv")
+    .args(args)
+    .current_dir(Path::new("/"))
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+  {
+    Ok(_child) => _child,
+    Err(msg) => return Err(format!("failed to execute gpgv: {}", msg)),
+  };
+
+  // write in another thread not to be blocked by full stdout pipe.
+  let mut stdin = child.stdin.take().unwrap();
+  let input = input.to_vec();
+  let writer = std::thread::spawn(move || stdin.write_all(&input));
+  let output = child.wait_with_output().unwrap();
+  writer.join().unwrap().ok();
+
+  if !output.status.success() {
+    let errstr = String::from_utf8_lossy(&output.stderr);
+    return Err(format!(
+      "signature verification failed:\n{}",
+      errstr.trim_end()
+    ));
+  }
+
+  Ok(output.stdout)
+}
+
+#[cfg(test)]
+pub mod test {
+  fn trusted() -> Vec<String> {
+    super::collect_keyrings(
+      "test/keyrings",
+      &Some("test/keyrings/rapt-test.gpg".to_string()),
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn test_collect_keyrings() {
+    let keyrings = super::collect_keyrings("test/keyrings", &None).unwrap();
+    assert_eq!(keyrings.len(), 2);
+    assert!(keyrings.iter().all(|k| k.starts_with('/')));
+    assert!(super::collect_keyrings("test/no-such-dir", &None).is_err());
+    assert!(
+      super::collect_keyrings("test/keyrings", &Some("test/no-such.gpg".to_string())).is_err()
+    );
+  }
+
+  #[test]
+  fn test_verify_inline() {
+    let inrelease = std::fs::read("test/repo/dists/focal/InRelease").unwrap();
+    let release = std::fs::read_to_string("test/repo/dists/focal/Release").unwrap();
+    assert_eq!(
+      super::verify_inline(&inrelease, &trusted()).unwrap(),
+      release
+    );
+
+    let tampered = String::from_utf8(inrelease.clone())
+      .unwrap()
+      .replace("Suite: focal", "Suite: hirsute");
+    assert!(super::verify_inline(tampered.as_bytes(), &trusted()).is_err());
+
+    let untrusted = super::collect_keyrings(
+      "test/keyrings",
+      &Some("test/keyrings/rapt-untrusted.gpg".to_string()),
+    )
+    .unwrap();
+    assert!(super::verify_inline(&inrelease, &untrusted).is_err());
+  }
+
+  #[test]
+  fn test_strip_inline() {
+    let inrelease = std::fs::read("test/repo/dists/focal/InRelease").unwrap();
+    let release = std::fs::read_to_string("test/repo/dists/focal/Release").unwrap();
+    assert_eq!(super::strip_inline(&inrelease).unwrap(), release);
+    assert!(super::strip_inline(release.as_bytes()).is_err());
+  }
+
+  #[test]
+  fn test_verify_detached() {
+    let release = std::fs::read("test/repo/dists/focal/Release").unwrap();
+    let signature = std::fs::read("test/repo/dists/focal/Release.gpg").unwrap();
+    assert!(super::verify_detached(&release, &signature, &trusted()).is_ok());
+    assert!(super::verify_detached(b"Suite: hirsute\n", &signature, &trusted()).is_err());
+  }
+}
@@ -1,7 +1,9 @@
-use crate::dpkg::PackageState;
+use crate::config::CONFIG;
+use crate::dpkg::DependencyFlags;
+use crate::edsp::Solver;
 use crate::lock::{get_lock, Lock};
-use crate::source::SourcePackage;
-use crate::{cache, dpkg, fetcher};
+use crate::source::{split_qualified_name, RelOp, SourcePackage, StatusStatus};
+use crate::{cache, dpkg, edsp, fetcher, order, resolver, source};
 use colored::*;
 use flate2::read::GzDecoder;
 use glob;
@@ -14,7 +16,7 @@ use std::path;
 use std::sync::mpsc;
 use xz2::read::XzDecoder;
 
-pub fn do_install(package: &str) {
+pub fn do_install(package: &str, flags: DependencyFlags, solver: &Solver) {
   let deb_regex = Regex::new(r"^.+\.deb$").unwrap();
   if deb_regex.is_match(package) {
     let debpath = path::Path::new(package);
@@ -22,7 +24,7 @@ pub fn do_install(package: &str) {
       println!("No such file: {}", debpath.to_str().unwrap().to_string());
       return;
     }
-    match install_debs(&vec![&debpath]) {
+    match install_debs(&vec![&debpath], flags, solver) {
       Ok(_) => {}
       Err(msg) => {
         println!("{}", msg);
@@ -30,49 +32,143 @@ pub fn do_install(package: &str) {
       }
     }
   } else {
-    // search package information from cache
-    let _target_package =
-      &cache::search_cache_with_name_glob(&glob::Pattern::new(package).unwrap(), true);
-    if _target_package.len() == 0 {
-      println!(
-        "Package {} is not in cache. \nDo 'rapt update' or add sources.list.",
-        package.green()
-      );
-      return;
-    }
+    // search package information from cache. 'pkg:arch' selects the architecture,
+    // and native one is used otherwise.
+    let (name, arch) = split_qualified_name(package);
+    let arch = arch.unwrap_or(&CONFIG.architecture);
+    let name_glob = glob::Pattern::new(name).unwrap();
+    let (_target_package, providers): (Vec<_>, Vec<_>) =
+      cache::search_cache_with_name_glob(&name_glob, true)
+        .into_iter()
+        .filter(|p| p.matches_arch(arch))
+        .partition(|p| name_glob.matches(&p.package));
+    // purely virtual package is installed by one of its providers.
+    let target_package = match _target_package.into_iter().next() {
+      Some(_target_package) => _target_package,
+      None if !providers.is_empty() => match choose_provider(name, providers) {
+        Ok(_provider) => _provider,
+        Err(msg) => {
+          println!("{}", msg);
+          return;
+        }
+      },
+      None => {
+        println!(
+          "Package {} is not in cache. \nDo 'rapt update' or add sources.list.",
+          package.green()
+        );
+        return;
+      }
+    };
     // check the package status
-    let target_package = &_target_package[0];
+    let target_package = &target_package;
     let progress_bar = ProgressBar::new(0);
     progress_bar
       .set_style(ProgressStyle::default_bar().template("Checking dependencies: {bar:40} {msg}"));
+    if let Err(msg) = dpkg::check_coinstallable(target_package) {
+      println!("{}", msg);
+      return;
+    }
     match dpkg::check_missing_or_old(
-      &target_package.package,
-      &Some(target_package.version.clone()),
+      &target_package.arch_qualified_name(),
+      &Some((RelOp::GE, target_package.version.clone())),
+      &target_package.arch_name(),
       Some(&progress_bar),
     )
     .unwrap()
     {
-      dpkg::PackageState::MISSING => match install_package(&target_package) {
+      dpkg::PackageState::MISSING => match install_package(target_package, flags, solver) {
         Ok(()) => {}
         Err(msg) => {
           println!("{}", msg);
           return;
         }
       },
-      dpkg::PackageState::UPTODATE | dpkg::PackageState::OLD => {
+      dpkg::PackageState::UPTODATE | dpkg::PackageState::OLD | dpkg::PackageState::CONFLICT => {
         println!(
           "Package {} is already installed.",
-          target_package.package.green()
+          target_package.qualified_name().green()
         );
+        // explicitly requested package is not a dependency anymore.
+        if source::is_auto_installed(target_package) {
+          match source::write_extended_information(
+            source::EXTENDED_STATES,
+            &[(target_package, false)],
+          ) {
+            Ok(()) => println!(
+              "{} set to manually installed.",
+              target_package.qualified_name()
+            ),
+            Err(msg) => println!("{}", msg),
+          }
+        }
       }
     }
   }
 }
 
+// @ret: provider of virtual package @name chosen by the user among @providers.
+fn choose_provider(name: &str, providers: Vec<SourcePackage>) -> Result<SourcePackage, String> {
+  if providers.len() == 1 {
+    println!(
+      "Note, selecting {} instead of virtual package {}",
+      providers[0].qualified_name().green(),
+      name
+    );
+    return Ok(providers.into_iter().next().unwrap());
+  }
+
+  println!(
+    "Package {} is a virtual package provided by:",
+    name.green().bold()
+  );
+  for (ix, provider) in providers.iter().enumerate() {
+    println!(
+      "  {}) {} {}",
+      ix + 1,
+      provider.qualified_name().green(),
+      provider.version
+    );
+  }
+  print!("Which one do you want to install? [1-{}] ", providers.len());
+  std::io::stdout().flush().unwrap();
+  let mut user_choice = String::new();
+  std::io::stdin()
+    .read_line(&mut user_choice)
+    .expect("invalid input");
+  match user_choice.trim().parse::<usize>() {
+    Ok(ix) if ix >= 1 && ix <= providers.len() => Ok(providers.into_iter().nth(ix - 1).unwrap()),
+    _ => Err("Abort.".to_string()),
+  }
+}
+
 // warning: this @packages should have 'Filename" field.
 //          it means that package should be re-searched in cachefiles
 //          (control dpkg/status or controlfile doesn't have this filed.)
-pub fn install_packages(packages: Vec<&SourcePackage>) -> Result<(), String> {
+// with @allow_changes false, packages other than @packages may be neither installed nor removed.
+pub fn install_packages(
+  packages: Vec<&SourcePackage>,
+  flags: DependencyFlags,
+  solver: &Solver,
+  allow_changes: bool,
+) -> Result<(), String> {
+  if let Solver::DUMP(path) = solver {
+    return dump_scenario(
+      path,
+      &packages.into_iter().cloned().collect::<Vec<_>>(),
+      flags,
+    );
+  }
+  // refused plan is found before fetching anything.
+  if !allow_changes {
+    let requests = packages.iter().map(|p| (*p).clone()).collect::<Vec<_>>();
+    let (installed, cached) = (&*source::DPKG_CACHE, &*source::CACHE);
+    let solution = match solver {
+      Solver::EXTERNAL(solver) => edsp::solve(solver, &requests, installed, cached, flags)?,
+      _ => resolver::resolve(&requests, installed, cached, flags)?,
+    };
+    refuse_changes(&requests, &solution)?;
+  }
   // install target package's deb
   let progress_bar = ProgressBar::new(0);
   progress_bar.set_style(
@@ -87,19 +183,34 @@ pub fn install_packages(packages: Vec<&SourcePackage>) -> Result<(), String> {
   // fetch deb files
   let mut debs = vec![];
   for package in packages {
-    let debname = match fetcher::fetch_deb(&package, Some(&progress_bar)) {
-      Ok(_debname) => _debname.0,
+    let (debpath, cached) = match fetcher::fetch_deb(package, Some(&progress_bar)) {
+      Ok(_debname) => (_debname.0, _debname.2),
       Err(msg) => return Err(msg),
     };
-    println!("fetched {} into {}", package.package, debname);
-    debs.push(format!("archive/{}", debname));
+    if cached {
+      println!("using cached {} in {}", package.qualified_name(), debpath);
+    } else {
+      println!("fetched {} into {}", package.qualified_name(), debpath);
+    }
+    debs.push(debpath);
   }
   lock.unlock().unwrap();
 
-  install_debs(&debs.iter().map(|d| path::Path::new(d)).collect::<Vec<_>>())
+  install_debs(
+    &debs.iter().map(path::Path::new).collect::<Vec<_>>(),
+    flags,
+    solver,
+  )
 }
 
-pub fn install_package(package: &SourcePackage) -> Result<(), String> {
+pub fn install_package(
+  package: &SourcePackage,
+  flags: DependencyFlags,
+  solver: &Solver,
+) -> Result<(), String> {
+  if let Solver::DUMP(path) = solver {
+    return dump_scenario(path, std::slice::from_ref(package), flags);
+  }
   // install target package's deb
   let progress_bar = ProgressBar::new(0);
   progress_bar.set_style(
@@ -112,17 +223,58 @@ pub fn install_package(package: &SourcePackage) -> Result<(), String> {
   let lock = get_lock(Lock::ARCHIVE)?;
 
   // fetch deb file
-  let debname = match fetcher::fetch_deb(&package, Some(&progress_bar)) {
-    Ok(_debname) => _debname.0,
+  let (debpath, cached) = match fetcher::fetch_deb(package, Some(&progress_bar)) {
+    Ok(_debname) => (_debname.0, _debname.2),
     Err(msg) => return Err(msg),
   };
-  println!("fetched {} into {}", package.package, debname);
+  if cached {
+    println!("using cached {} in {}", package.qualified_name(), debpath);
+  } else {
+    println!("fetched {} into {}", package.qualified_name(), debpath);
+  }
   lock.unlock().unwrap();
 
-  install_debs(&vec![&path::Path::new(&format!("archive/{}", debname))])
+  install_debs(&vec![&path::Path::new(&debpath)], flags, solver)
+}
+
+// @ret: error if @solution installs packages other than @requests, or removes any.
+fn refuse_changes(requests: &[SourcePackage], solution: &resolver::Solution) -> Result<(), String> {
+  let changes = solution
+    .installs
+    .iter()
+    .filter(|i| {
+      !requests
+        .iter()
+        .any(|r| r.arch_qualified_name() == i.arch_qualified_name())
+    })
+    .chain(solution.removals.iter())
+    .map(|p| p.qualified_name())
+    .collect::<Vec<_>>();
+  if changes.is_empty() {
+    return Ok(());
+  }
+  Err(format!(
+    "{} would have to be installed or removed. use 'full-upgrade' instead.",
+    changes.join(" ")
+  ))
+}
+
+// write the scenario to install @packages into @path, without fetching anything.
+fn dump_scenario(
+  path: &str,
+  packages: &[SourcePackage],
+  flags: DependencyFlags,
+) -> Result<(), String> {
+  edsp::dump(path, packages, &source::DPKG_CACHE, &source::CACHE, flags)?;
+  println!("EDSP scenario is written into {}", path);
+  Ok(())
 }
 
-pub fn install_debs(debfiles: &Vec<&path::Path>) -> Result<(), String> {
+pub fn install_debs(
+  debfiles: &Vec<&path::Path>,
+  flags: DependencyFlags,
+  solver: &Solver,
+) -> Result<(), String> {
   let tmp_workdir = path::Path::new("tmp");
   if !tmp_workdir.exists() {
     return Err("temporary working directory 'tmp' doesn't exist.".to_string());
@@ -138,44 +290,40 @@ pub fn install_debs(debfiles: &Vec<&path::Path>) -> Result<(), String> {
   let packages = &cache::search_cache_with_names(
     &_packages
       .iter()
-      .map(|p| p.package.clone())
+      .map(|p| p.arch_qualified_name())
       .collect::<Vec<_>>(),
   );
-  println!("\nRecursively searching for dependencies: ");
-  let mut missing_old_package_names: Vec<(String, PackageState)> = vec![];
-  for p in packages {
-    missing_old_package_names.append(&mut dpkg::get_missing_or_old_dependencies_recursive(
-      p, true,
-    )?);
-  }
-
-  let missing_package_names = missing_old_package_names
-    .iter()
-    .filter(|c| c.1 == dpkg::PackageState::MISSING)
+  println!("\nResolving dependencies: ");
+  let (installed, cached) = (&*source::DPKG_CACHE, &*source::CACHE);
+  let solution = match solver {
+    Solver::INTERNAL => resolver::resolve(packages, installed, cached, flags)?,
+    Solver::EXTERNAL(solver) => edsp::solve(solver, packages, installed, cached, flags)?,
+    Solver::DUMP(path) => return dump_scenario(path, packages, flags),
+  };
+  // requested packages are installed from @debfiles.
+  let requested = |p: &SourcePackage| {
+    packages
+      .iter()
+      .any(|r| r.arch_qualified_name() == p.arch_qualified_name())
+  };
+  let missing_packages = solution
+    .installs
+    .into_iter()
+    .filter(|p| !requested(p))
     .collect::<Vec<_>>();
-  let old_package_names = missing_old_package_names
-    .iter()
-    .filter(|c| c.1 == dpkg::PackageState::OLD)
+  let old_packages = solution
+    .upgrades
+    .into_iter()
+    .filter(|p| !requested(p))
     .collect::<Vec<_>>();
-  let missing_packages = cache::search_cache_with_names(
-    &missing_package_names
-      .iter()
-      .map(|item| item.0.clone())
-      .collect(),
-  );
-  let old_packages = cache::search_cache_with_names(
-    &old_package_names
-      .iter()
-      .map(|item| item.0.clone())
-      .collect(),
-  );
+  let removals = solution.removals;
 
   print!("\nThe following additional packages will be installed: \n  ");
   for mp in &missing_packages {
-    print!("{} ", mp.package);
+    print!("{} ", mp.qualified_name());
   }
   for mp in &old_packages {
-    print!("{} ", mp.package);
+    print!("{} ", mp.qualified_name());
   }
   println!("");
 
@@ -184,22 +332,30 @@ pub fn install_debs(debfiles: &Vec<&path::Path>) -> Result<(), String> {
     "{} ",
     packages
       .iter()
-      .map(|p| p.package.clone())
+      .map(|p| p.qualified_name())
       .collect::<Vec<_>>()
       .join(" ")
       .green()
       .bold()
   );
   for mp in &missing_packages {
-    print!("{} ", mp.package.green());
+    print!("{} ", mp.qualified_name().green());
   }
   println!("");
 
+  if !removals.is_empty() {
+    print!("The following packages will be REMOVED: \n  ");
+    for rp in &removals {
+      print!("{} ", rp.qualified_name().red());
+    }
+    println!();
+  }
+
   println!(
     "{} upgraded, {} newly installed, {} to remove and {} not upgraded.",
     old_packages.len(),
     missing_packages.len() + 1,
-    "?",
+    removals.len(),
     "?"
   );
   println!("Need to get {} kB of archives.", "?");
@@ -222,6 +378,7 @@ pub fn install_debs(debfiles: &Vec<&path::Path>) -> Result<(), String> {
   let lock = get_lock(Lock::ARCHIVE)?;
 
   // download all missing dependencies
+  let start_time = std::time::SystemTime::now();
   let mut handles = vec![];
   let (tx, rx) = mpsc::channel();
   let progress_bars = MultiProgress::new();
@@ -240,8 +397,8 @@ pub fn install_debs(debfiles: &Vec<&path::Path>) -> Result<(), String> {
     progress_bar.set_style(progress_style.clone());
 
     let handle = std::thread::spawn(move || match fetcher::fetch_deb(&md, Some(&progress_bar)) {
-      Ok((_filename, fetched_size)) => {
-        tx.send(Ok(fetched_size)).unwrap();
+      Ok((_filename, fetched_size, cached)) => {
+        tx.send(Ok((fetched_size, cached))).unwrap();
       }
       Err(msg) => {
         tx.send(Err(msg)).unwrap();
@@ -250,12 +407,16 @@ pub fn install_debs(debfiles: &Vec<&path::Path>) -> Result<(), String> {
     handles.push(handle);
   }
 
-  let mut fetched_amount = 0;
+  let mut fetched_amount: u64 = 0;
+  let mut cached_count = 0;
   progress_bars.join().unwrap();
   for handle in handles {
     match rx.recv().unwrap() {
-      Ok(fetched_size) => {
+      Ok((fetched_size, cached)) => {
         fetched_amount += fetched_size;
+        if cached {
+          cached_count += 1;
+        }
       }
       Err(msg) => {
         println!("{}", msg);
@@ -264,25 +425,68 @@ pub fn install_debs(debfiles: &Vec<&path::Path>) -> Result<(), String> {
     }
     handle.join().unwrap();
   }
-  println!("Fetched {} kB in ?s (? kB/s)", fetched_amount / 1000);
+  let total_time = start_time.elapsed().unwrap().as_secs();
+  let fetched_amount_kb = fetched_amount / 1000;
+  let bps = fetched_amount_kb
+    .checked_div(total_time)
+    .unwrap_or(fetched_amount_kb);
+  println!(
+    "Fetched {} kB in {}s ({} kB/s), {} cached",
+    fetched_amount_kb, total_time, bps, cached_count
+  );
   lock.unlock().unwrap();
 
-  // install dependencies
-  for (_ix, md) in missing_packages
+  // remove conflicting packages before dpkg refuses the installation.
+  if !removals.is_empty() {
+    let names = removals
+      .iter()
+      .map(|p| p.qualified_name())
+      .collect::<Vec<_>>()
+      .join(" ");
+    println!("removing {} ...", names.red());
+    dpkg::remove_packages(&removals, false, false)?;
+  }
+
+  // install dependencies and targets in order of dependency.
+  let planned = missing_packages
     .iter()
     .chain(old_packages.iter())
-    .rev()
-    .enumerate()
-  {
-    println!("installing {} ...", md.package.green());
-    dpkg::install_archived_package(&md)?;
+    .chain(packages.iter())
+    .cloned()
+    .collect::<Vec<_>>();
+  for step in order::batch_steps(order::order_installation(&planned)?) {
+    let names = step
+      .packages
+      .iter()
+      .map(|p| p.qualified_name())
+      .collect::<Vec<_>>()
+      .join(" ");
+    match step.action {
+      order::Action::UNPACK => {
+        println!("unpacking {} ...", names.green());
+        dpkg::unpack_archived_packages(&step.packages)?;
+      }
+      order::Action::CONFIGURE => {
+        println!("configuring {} ...", names.green().bold());
+        dpkg::configure_packages(&step.packages)?;
+      }
+    }
   }
 
-  // install target
-  for p in packages {
-    println!("installing {} ...", p.package.green().bold());
-    dpkg::install_archived_package(&p)?;
-  }
+  // record why packages are installed. upgraded packages keep their marks.
+  let newly_installed = |p: &SourcePackage| {
+    !installed.iter().any(|i| {
+      i.status.status == StatusStatus::INSTALLED
+        && i.arch_qualified_name() == p.arch_qualified_name()
+    })
+  };
+  let marks = packages
+    .iter()
+    .filter(|p| newly_installed(p))
+    .map(|p| (p, false))
+    .chain(missing_packages.iter().map(|p| (p, true)))
+    .collect::<Vec<_>>();
+  source::write_extended_information(source::EXTENDED_STATES, &marks)?;
 
   println!("{}", "Install complete.".yellow().bold());
 
@@ -296,11 +500,16 @@ pub mod test {
     let package = "vim-common";
     let items =
       crate::cache::search_cache_with_name_glob(&glob::Pattern::new(package).unwrap(), true);
-    let missing = crate::dpkg::get_missing_or_old_dependencies(&items[0], true);
+    let missing = crate::resolver::resolve(
+      &items[0..1],
+      &crate::source::DPKG_CACHE,
+      &crate::source::CACHE,
+      Default::default(),
+    );
     println!("{:?}", missing);
     println!(
       "{:?}",
-      crate::dpkg::check_missing_or_old("xxd", &None, None)
+      crate::dpkg::check_missing_or_old("xxd", &None, "amd64", None)
     );
     panic!("");
   }
@@ -5,6 +5,15 @@ use glob::Pattern;
 use indicatif::{ProgressBar, ProgressStyle};
 
 pub fn do_list(package: &str, installed: bool, upgradable: bool) {
+  // 'pkg:arch' lists packages of the architecture only.
+  let (package, arch) = source::split_qualified_name(package);
+  let filter_arch = |items: Vec<SourcePackage>| match arch {
+    Some(arch) => items
+      .into_iter()
+      .filter(|i| i.matches_arch(arch))
+      .collect::<Vec<_>>(),
+    None => items,
+  };
   if installed {
     let installed_items = &*source::DPKG_CACHE;
     // 'apt list' uses glob pattern instead of regex.
@@ -15,7 +24,7 @@ pub fn do_list(package: &str, installed: bool, upgradable: bool) {
         return;
       }
     };
-    let mut found_items = filter_package_with_name(&package_glob, &installed_items);
+    let mut found_items = filter_arch(filter_package_with_name(&package_glob, installed_items));
     list_packages(&mut found_items);
   } else if upgradable {
     let progress_bar = ProgressBar::new(0);
@@ -40,7 +49,7 @@ pub fn do_list(package: &str, installed: bool, upgradable: bool) {
         return;
       }
     };
-    let mut found_items = cache::search_cache_with_name_glob(&package_glob, false);
+    let mut found_items = filter_arch(cache::search_cache_with_name_glob(&package_glob, false));
     list_packages(&mut found_items);
   }
 }
@@ -59,10 +68,7 @@ pub fn filter_package_with_name(glob: &Pattern, items: &Vec<SourcePackage>) -> V
 pub fn list_packages(items: &mut Vec<SourcePackage>) {
   for item in items {
     // first, check if it is installed and auto-installed
-    let auto_installed = match source::EXTENDED_CACHE.iter().find(|e| e.0 == item.package) {
-      Some(info) => info.1,
-      None => false,
-    };
+    let auto_installed = source::is_auto_installed(item);
     let installed = if cache::search_cache_with_name_glob(
       &glob::Pattern::new(&item.package).unwrap(),
       true,
@@ -80,10 +86,16 @@ pub fn list_packages(items: &mut Vec<SourcePackage>) {
       Some(_tmp) => _tmp.to_string(),
       None => "".to_string(),
     };
+    // flat repository doesn't have components.
+    let component = if item.component.is_empty() {
+      &item.dist
+    } else {
+      &item.component
+    };
     print!(
       "{}/{} {} {} ",
-      item.package.green().bold(),
-      item.component,
+      item.qualified_name().green().bold(),
+      component,
       item.version,
       arch,
     );
@@ -2,17 +2,27 @@ use colored::*;
 use simple_logger::SimpleLogger;
 
 pub mod cache;
+pub mod chksum;
 pub mod clean;
 mod cli;
+pub mod config;
 pub mod dpkg;
+pub mod edsp;
 pub mod fetcher;
+pub mod gpgv;
 pub mod install;
 pub mod list;
 pub mod lock;
+pub mod mark;
+pub mod order;
+pub mod release;
+pub mod remove;
+pub mod resolver;
 pub mod search;
 pub mod show;
 pub mod slist;
 pub mod source;
+pub mod transport;
 pub mod update;
 pub mod upgrade;
 pub mod version;
@@ -24,6 +34,14 @@ pub struct Opts {
   pub upgradabe: bool,
   pub full_description: bool,
   pub package: String,
+  pub packages: Vec<String>,
+  pub allow_remove_essential: bool,
+  pub dry_run: bool,
+  pub mark_action: mark::MarkAction,
+  pub trusted_dir: String,
+  pub source_parts: String,
+  pub dependency_flags: dpkg::DependencyFlags,
+  pub solver: edsp::Solver,
 }
 
 #[derive(Debug, PartialEq)]
@@ -35,6 +53,11 @@ pub enum Command {
   INSTALL,
   CLEAN,
   UPGRADE,
+  FULLUPGRADE,
+  REMOVE,
+  PURGE,
+  AUTOREMOVE,
+  MARK,
   UNKNOWN,
 }
 
@@ -57,7 +80,7 @@ fn main() {
 
   match opts.command {
     Command::UPDATE => {
-      update::do_update();
+      update::do_update(&opts.trusted_dir, &opts.source_parts);
     }
     Command::LIST => {
       list::do_list(&opts.package, opts.installed, opts.upgradabe);
@@ -69,13 +92,28 @@ fn main() {
       show::do_show(&opts.package);
     }
     Command::INSTALL => {
-      install::do_install(&opts.package);
+      install::do_install(&opts.package, opts.dependency_flags, &opts.solver);
     }
     Command::CLEAN => {
       clean::do_clean();
     }
     Command::UPGRADE => {
-      upgrade::do_upgrade();
+      upgrade::do_upgrade(opts.dependency_flags, &opts.solver, false);
+    }
+    Command::FULLUPGRADE => {
+      upgrade::do_upgrade(opts.dependency_flags, &opts.solver, true);
+    }
+    Command::REMOVE => {
+      remove::do_remove(&opts.packages, false, opts.allow_remove_essential);
+    }
+    Command::PURGE => {
+      remove::do_remove(&opts.packages, true, opts.allow_remove_essential);
+    }
+    Command::AUTOREMOVE => {
+      remove::do_autoremove(opts.dry_run);
+    }
+    Command::MARK => {
+      mark::do_mark(&opts.mark_action, &opts.packages);
     }
     Command::UNKNOWN => {
       println!("Unknown subcommand");
@@ -86,9 +124,19 @@ fn main() {
 pub fn parse_opts(opts: &mut Opts) {
   let matches = cli::build_cli().get_matches();
 
-  if let Some(ref _matches) = matches.subcommand_matches("update") {
+  if let Some(matches) = matches.subcommand_matches("update") {
     log::trace!("subcommand: update");
     opts.command = Command::UPDATE;
+    opts.trusted_dir = matches
+      .value_of("trusted-dir")
+      .unwrap_or(gpgv::DEFAULT_TRUSTED_DIR)
+      .to_string();
+    opts.source_parts = matches
+      .value_of("source-parts")
+      .unwrap_or(slist::DEFAULT_SOURCE_PARTS)
+      .to_string();
+    log::trace!("trusted dir: {}", opts.trusted_dir);
+    log::trace!("source parts dir: {}", opts.source_parts);
   } else if let Some(ref matches) = matches.subcommand_matches("list") {
     log::trace!("subcommand: list");
     opts.command = Command::LIST;
@@ -120,16 +168,86 @@ pub fn parse_opts(opts: &mut Opts) {
   } else if let Some(ref _matches) = matches.subcommand_matches("clean") {
     log::trace!("subcommand: clean");
     opts.command = Command::CLEAN;
-  } else if let Some(ref _matches) = matches.subcommand_matches("upgrade") {
+  } else if let Some(matches) = matches.subcommand_matches("upgrade") {
     log::trace!("subcommand: upgrade");
     opts.command = Command::UPGRADE;
+    parse_install_opts(matches, opts);
+  } else if let Some(matches) = matches.subcommand_matches("full-upgrade") {
+    log::trace!("subcommand: full-upgrade");
+    opts.command = Command::FULLUPGRADE;
+    parse_install_opts(matches, opts);
   } else if let Some(ref matches) = matches.subcommand_matches("install") {
     log::trace!("subcommand: install");
     opts.command = Command::INSTALL;
     opts.package = matches.value_of("package").unwrap().to_string();
+    parse_install_opts(matches, opts);
     log::trace!("package: {}", opts.package);
+  } else if let Some(matches) = matches.subcommand_matches("remove") {
+    log::trace!("subcommand: remove");
+    opts.command = Command::REMOVE;
+    parse_remove_opts(matches, opts);
+  } else if let Some(matches) = matches.subcommand_matches("purge") {
+    log::trace!("subcommand: purge");
+    opts.command = Command::PURGE;
+    parse_remove_opts(matches, opts);
+  } else if let Some(matches) = matches.subcommand_matches("autoremove") {
+    log::trace!("subcommand: autoremove");
+    opts.command = Command::AUTOREMOVE;
+    opts.dry_run = matches.is_present("dry-run");
+  } else if let Some(matches) = matches.subcommand_matches("mark") {
+    log::trace!("subcommand: mark");
+    opts.command = Command::MARK;
+    let (action, matches) = match matches.subcommand() {
+      ("auto", Some(_matches)) => (mark::MarkAction::AUTO, _matches),
+      ("manual", Some(_matches)) => (mark::MarkAction::MANUAL, _matches),
+      ("showauto", Some(_matches)) => (mark::MarkAction::SHOWAUTO, _matches),
+      ("showmanual", Some(_matches)) => (mark::MarkAction::SHOWMANUAL, _matches),
+      ("hold", Some(_matches)) => (mark::MarkAction::HOLD, _matches),
+      ("unhold", Some(_matches)) => (mark::MarkAction::UNHOLD, _matches),
+      ("showhold", Some(_matches)) => (mark::MarkAction::SHOWHOLD, _matches),
+      _ => (mark::MarkAction::UNKNOWN, matches),
+    };
+    opts.mark_action = action;
+    opts.packages = matches
+      .values_of("package")
+      .map(|v| v.map(|p| p.to_string()).collect())
+      .unwrap_or_default();
+    log::trace!("mark: {:?} {:?}", opts.mark_action, opts.packages);
   } else {
     log::trace!("not implemented subcommand");
     opts.command = Command::UNKNOWN;
   }
 }
+
+// options shared by subcommands which install packages. defaults come from rapt.conf.
+fn parse_install_opts(matches: &clap::ArgMatches, opts: &mut Opts) {
+  opts.dependency_flags = dpkg::DependencyFlags {
+    recommends: config::CONFIG.install_recommends && !matches.is_present("no-install-recommends"),
+    suggests: config::CONFIG.install_suggests || matches.is_present("install-suggests"),
+  };
+  log::trace!("dependency flags: {:?}", opts.dependency_flags);
+  opts.solver = match (
+    matches.value_of("dump-scenario"),
+    matches.value_of("solver"),
+  ) {
+    (Some(path), _) => edsp::Solver::DUMP(path.to_string()),
+    (None, Some("internal")) => edsp::Solver::INTERNAL,
+    (None, Some(solver)) => edsp::Solver::EXTERNAL(solver.to_string()),
+    (None, None) => match &config::CONFIG.external_solver {
+      Some(solver) => edsp::Solver::EXTERNAL(solver.clone()),
+      None => edsp::Solver::INTERNAL,
+    },
+  };
+  log::trace!("solver: {:?}", opts.solver);
+}
+
+// options shared by subcommands which remove packages.
+fn parse_remove_opts(matches: &clap::ArgMatches, opts: &mut Opts) {
+  opts.packages = matches
+    .values_of("package")
+    .unwrap()
+    .map(|p| p.to_string())
+    .collect();
+  opts.allow_remove_essential = matches.is_present("allow-remove-essential");
+  log::trace!("packages: {:?}", opts.packages);
+}
@@ -0,0 +1,150 @@
+use crate::dpkg;
+use crate::source::{self, SourcePackage, StatusStatus};
+
+#[derive(Debug, PartialEq, Default)]
+pub enum MarkAction {
+  AUTO,
+  MANUAL,
+  SHOWAUTO,
+  SHOWMANUAL,
+  HOLD,
+  UNHOLD,
+  SHOWHOLD,
+  #[default]
+  UNKNOWN,
+}
+
+pub fn do_mark(action: &MarkAction, packages: &[String]) {
+  match action {
+    MarkAction::AUTO => mark_auto(packages, true),
+    MarkAction::MANUAL => mark_auto(packages, false),
+    MarkAction::SHOWAUTO => show_auto(packages, true),
+    MarkAction::SHOWMANUAL => show_auto(packages, false),
+    MarkAction::HOLD => mark_hold(packages, true),
+    MarkAction::UNHOLD => mark_hold(packages, false),
+    MarkAction::SHOWHOLD => show_hold(packages),
+    MarkAction::UNKNOWN => {
+      println!("unknown mark action.");
+    }
+  }
+}
+
+// @ret: installed packages matching @packages('pkg' or 'pkg:arch').
+fn find_installed(packages: &[String]) -> Vec<&'static SourcePackage> {
+  let mut founds = vec![];
+  for package in packages {
+    let (name, arch) = source::split_qualified_name(package);
+    let matched = source::DPKG_CACHE
+      .iter()
+      .filter(|i| i.status.status == StatusStatus::INSTALLED)
+      .filter(|i| {
+        i.package == name
+          && match arch {
+            Some(arch) => i.matches_arch(arch),
+            None => true,
+          }
+      })
+      .collect::<Vec<_>>();
+    if matched.is_empty() {
+      println!("Package {} is not installed, so not marked.", package);
+    }
+    founds.extend(matched);
+  }
+  founds
+}
+
+// set @packages as automatically installed if @auto, or manually installed.
+fn mark_auto(packages: &[String], auto: bool) {
+  let reason = if auto { "automatically" } else { "manually" };
+  let mut marks = vec![];
+  for package in find_installed(packages) {
+    if source::is_auto_installed(package) == auto {
+      println!(
+        "{} was already set to {} installed.",
+        package.qualified_name(),
+        reason
+      );
+    } else {
+      marks.push((package, auto));
+    }
+  }
+  if marks.is_empty() {
+    return;
+  }
+
+  match source::write_extended_information(source::EXTENDED_STATES, &marks) {
+    Ok(()) => {
+      for (package, _) in marks {
+        println!("{} set to {} installed.", package.qualified_name(), reason);
+      }
+    }
+    Err(msg) => println!("{}", msg),
+  }
+}
+
+// list installed packages which are automatically installed if @auto, or manually installed.
+fn show_auto(packages: &[String], auto: bool) {
+  show_packages(packages, |p| source::is_auto_installed(p) == auto);
+}
+
+// list installed packages matching @filter. only @packages are shown if given.
+fn show_packages(packages: &[String], filter: impl Fn(&SourcePackage) -> bool) {
+  let installed = if packages.is_empty() {
+    source::DPKG_CACHE
+      .iter()
+      .filter(|i| i.status.status == StatusStatus::INSTALLED)
+      .collect::<Vec<_>>()
+  } else {
+    find_installed(packages)
+  };
+  let mut names = installed
+    .into_iter()
+    .filter(|i| filter(i))
+    .map(|i| i.qualified_name())
+    .collect::<Vec<_>>();
+  names.sort();
+  names.dedup();
+  for name in names {
+    println!("{}", name);
+  }
+}
+
+// put @packages on hold if @hold, or cancel it, via dpkg selections.
+fn mark_hold(packages: &[String], hold: bool) {
+  let mut selections = vec![];
+  let mut marked = vec![];
+  for package in find_installed(packages) {
+    if package.is_held() == hold {
+      if hold {
+        println!("{} was already set on hold.", package.qualified_name());
+      } else {
+        println!("{} was already not on hold.", package.qualified_name());
+      }
+      continue;
+    }
+    let state = if hold { "hold" } else { "install" };
+    selections.push((package.arch_qualified_name(), state));
+    marked.push(package);
+  }
+  if selections.is_empty() {
+    return;
+  }
+
+  match dpkg::set_selections(&selections) {
+    Ok(()) => {
+      for package in marked {
+        if hold {
+          println!("{} set on hold.", package.qualified_name());
+        } else {
+          println!("Canceled hold on {}.", package.qualified_name());
+        }
+      }
+    }
+    Err(msg) => println!("{}", msg),
+  }
+}
+
+// list installed packages on hold.
+fn show_hold(packages: &[String]) {
+  show_packages(packages, |p| p.is_held());
+}
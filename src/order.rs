@@ -0,0 +1,419 @@
+use crate::source::{OrGroup, SourcePackage};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+  UNPACK,
+  CONFIGURE,
+}
+
+// a dpkg run over @packages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+  pub action: Action,
+  pub packages: Vec<SourcePackage>,
+}
+
+// dependency graph of the planned packages. edges point to dependencies.
+struct Graph {
+  depends: Vec<Vec<usize>>,
+  pre_depends: Vec<Vec<usize>>,
+}
+
+// @ret: dpkg steps to install @packages, in which dependencies are configured before
+//       their dependers, and Pre-Depends are configured even before unpacking the dependers.
+//       packages in a dependency cycle are unpacked all together and then configured.
+pub fn order_installation(packages: &[SourcePackage]) -> Result<Vec<Step>, String> {
+  let graph = Graph {
+    depends: packages
+      .iter()
+      .map(|p| dependencies(p, &p.depends, packages))
+      .collect(),
+    pre_depends: packages
+      .iter()
+      .map(|p| dependencies(p, &p.pre_depends, packages))
+      .collect(),
+  };
+
+  let mut steps = vec![];
+  for component in strongly_connected_components(&graph) {
+    for (action, members) in order_component(&graph, &component, packages)? {
+      steps.push(Step {
+        action,
+        packages: members.iter().map(|ix| packages[*ix].clone()).collect(),
+      });
+    }
+  }
+
+  Ok(steps)
+}
+
+// merge ordered @steps into fewer dpkg runs. unpacks are batched, and packages are
+// configured early only if later ones pre-depend on them, together with what they need.
+// everything else is configured at once at last.
+pub fn batch_steps(steps: Vec<Step>) -> Vec<Step> {
+  let mut batched: Vec<Step> = vec![];
+  let mut deferred: Vec<SourcePackage> = vec![];
+  for (ix, step) in steps.iter().enumerate() {
+    match step.action {
+      Action::UNPACK => match batched.last_mut() {
+        Some(last) if last.action == Action::UNPACK => last.packages.extend(step.packages.clone()),
+        _ => batched.push(step.clone()),
+      },
+      Action::CONFIGURE => {
+        deferred.extend(step.packages.clone());
+        let later = steps[ix + 1..]
+          .iter()
+          .filter(|s| s.action == Action::UNPACK)
+          .flat_map(|s| s.packages.iter())
+          .collect::<Vec<_>>();
+        let mut early = deferred
+          .iter()
+          .filter(|p| later.iter().any(|l| relies_on(l, &l.pre_depends, p)))
+          .cloned()
+          .collect::<Vec<_>>();
+        if early.is_empty() {
+          continue;
+        }
+        // dependencies of early ones must be configured with them.
+        loop {
+          let needed = deferred
+            .iter()
+            .filter(|d| !early.contains(d))
+            .filter(|d| {
+              early
+                .iter()
+                .any(|e| relies_on(e, &e.pre_depends, d) || relies_on(e, &e.depends, d))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+          if needed.is_empty() {
+            break;
+          }
+          early.extend(needed);
+        }
+        deferred.retain(|d| !early.contains(d));
+        batched.push(Step {
+          action: Action::CONFIGURE,
+          packages: deferred_order(&steps, early),
+        });
+      }
+    }
+  }
+  if !deferred.is_empty() {
+    batched.push(Step {
+      action: Action::CONFIGURE,
+      packages: deferred,
+    });
+  }
+
+  batched
+}
+
+// @ret: @packages sorted in order of configuration in @steps.
+fn deferred_order(steps: &[Step], mut packages: Vec<SourcePackage>) -> Vec<SourcePackage> {
+  let configured = steps
+    .iter()
+    .filter(|s| s.action == Action::CONFIGURE)
+    .flat_map(|s| s.packages.iter())
+    .collect::<Vec<_>>();
+  packages.sort_by_key(|p| configured.iter().position(|c| *c == p));
+  packages
+}
+
+// whether @groups of @package may be satisfied by @other.
+fn relies_on(package: &SourcePackage, groups: &[OrGroup], other: &SourcePackage) -> bool {
+  !dependencies(package, groups, std::slice::from_ref(other)).is_empty()
+}
+
+// @ret: indices of @packages which may satisfy @groups of @package.
+//       dependencies satisfied only by other packages are just ignored.
+fn dependencies(
+  package: &SourcePackage,
+  groups: &[OrGroup],
+  packages: &[SourcePackage],
+) -> Vec<usize> {
+  let arch = package.arch_name();
+  let mut ret = vec![];
+  for relation in groups.iter().flatten().filter(|r| r.applies_to_arch(&arch)) {
+    for (ix, other) in packages.iter().enumerate() {
+      if other.arch_qualified_name() != package.arch_qualified_name()
+        && other.satisfies_arch(relation.arch_qualifier.as_deref(), &arch)
+        && relation.satisfied_by(other)
+        && !ret.contains(&ix)
+      {
+        ret.push(ix);
+      }
+    }
+  }
+  ret
+}
+
+// Tarjan's algorithm over both Depends and Pre-Depends.
+// @ret: components, each of which comes after the components it depends on.
+fn strongly_connected_components(graph: &Graph) -> Vec<Vec<usize>> {
+  struct Tarjan<'a> {
+    graph: &'a Graph,
+    index: Vec<Option<usize>>,
+    lowlink: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    next_index: usize,
+    components: Vec<Vec<usize>>,
+  }
+
+  impl<'a> Tarjan<'a> {
+    fn visit(&mut self, v: usize) {
+      self.index[v] = Some(self.next_index);
+      self.lowlink[v] = self.next_index;
+      self.next_index += 1;
+      self.stack.push(v);
+      self.on_stack[v] = true;
+
+      let graph = self.graph;
+      for &w in graph.depends[v].iter().chain(graph.pre_depends[v].iter()) {
+        match self.index[w] {
+          None => {
+            self.visit(w);
+            self.lowlink[v] = self.lowlink[v].min(self.lowlink[w]);
+          }
+          Some(w_index) if self.on_stack[w] => {
+            self.lowlink[v] = self.lowlink[v].min(w_index);
+          }
+          Some(_) => {}
+        }
+      }
+
+      if Some(self.lowlink[v]) == self.index[v] {
+        let mut component = vec![];
+        loop {
+          let w = self.stack.pop().unwrap();
+          self.on_stack[w] = false;
+          component.push(w);
+          if w == v {
+            break;
+          }
+        }
+        component.sort_unstable();
+        self.components.push(component);
+      }
+    }
+  }
+
+  let size = graph.depends.len();
+  let mut tarjan = Tarjan {
+    graph,
+    index: vec![None; size],
+    lowlink: vec![0; size],
+    on_stack: vec![false; size],
+    stack: vec![],
+    next_index: 0,
+    components: vec![],
+  };
+  for v in 0..size {
+    if tarjan.index[v].is_none() {
+      tarjan.visit(v);
+    }
+  }
+  tarjan.components
+}
+
+// @ret: actions to install @component.
+//       members are unpacked in layers by Pre-Depends inside the component, and ones
+//       pre-depended by later layers are configured before them.
+fn order_component(
+  graph: &Graph,
+  component: &[usize],
+  packages: &[SourcePackage],
+) -> Result<Vec<(Action, Vec<usize>)>, String> {
+  let inner_pre_depends = |v: usize| {
+    graph.pre_depends[v]
+      .iter()
+      .filter(|w| component.contains(w))
+      .copied()
+      .collect::<Vec<_>>()
+  };
+
+  // layer of a member is the longest chain of Pre-Depends from it.
+  let mut layers: Vec<Vec<usize>> = vec![];
+  let mut placed: Vec<usize> = vec![];
+  while placed.len() < component.len() {
+    let layer = component
+      .iter()
+      .filter(|v| !placed.contains(v))
+      .filter(|v| inner_pre_depends(**v).iter().all(|w| placed.contains(w)))
+      .copied()
+      .collect::<Vec<_>>();
+    if layer.is_empty() {
+      let names = component
+        .iter()
+        .filter(|v| !placed.contains(v))
+        .map(|v| packages[*v].qualified_name())
+        .collect::<Vec<_>>();
+      return Err(format!(
+        "Unable to order installation: Pre-Depends loop among {}.",
+        names.join(", ")
+      ));
+    }
+    placed.extend(layer.iter());
+    layers.push(layer);
+  }
+
+  let mut actions = vec![];
+  let mut configured: Vec<usize> = vec![];
+  for (ix, layer) in layers.iter().enumerate() {
+    actions.push((Action::UNPACK, layer.clone()));
+    let later = layers[ix + 1..]
+      .iter()
+      .flatten()
+      .copied()
+      .collect::<Vec<_>>();
+    let pre_depended = if later.is_empty() {
+      // configure everything left at last.
+      component
+        .iter()
+        .filter(|v| !configured.contains(v))
+        .copied()
+        .collect::<Vec<_>>()
+    } else {
+      placed
+        .iter()
+        .filter(|v| !later.contains(v) && !configured.contains(v))
+        .filter(|v| later.iter().any(|l| inner_pre_depends(*l).contains(v)))
+        .copied()
+        .collect::<Vec<_>>()
+    };
+    if !pre_depended.is_empty() {
+      configured.extend(pre_depended.iter());
+      actions.push((Action::CONFIGURE, pre_depended));
+    }
+  }
+
+  Ok(actions)
+}
+
+#[cfg(test)]
+pub mod test {
+  use super::{order_installation, Action};
+  use crate::source::SourcePackage;
+
+  fn steps(raw: &str) -> Result<Vec<(Action, Vec<String>)>, String> {
+    let packages = SourcePackage::from_raw(raw, "").unwrap();
+    Ok(
+      order_installation(&packages)?
+        .into_iter()
+        .map(|s| {
+          (
+            s.action,
+            s.packages.iter().map(|p| p.package.clone()).collect(),
+          )
+        })
+        .collect(),
+    )
+  }
+
+  #[test]
+  fn test_batch_steps() {
+    use super::{batch_steps, order_installation};
+    let batched = |raw: &str| {
+      let packages = SourcePackage::from_raw(raw, "").unwrap();
+      batch_steps(order_installation(&packages).unwrap())
+        .into_iter()
+        .map(|s| {
+          let names = s
+            .packages
+            .iter()
+            .map(|p| p.package.as_str())
+            .collect::<Vec<_>>();
+          format!("{:?} {}", s.action, names.join(" "))
+        })
+        .collect::<Vec<_>>()
+    };
+
+    // unpacked at once, and configured at once.
+    assert_eq!(
+      batched(
+        "Package: app\nArchitecture: amd64\nVersion: 1\nDepends: lib\n\n\
+         Package: lib\nArchitecture: amd64\nVersion: 1\nDepends: base\n\n\
+         Package: base\nArchitecture: amd64\nVersion: 1\n"
+      ),
+      vec!["UNPACK base lib app", "CONFIGURE base lib app"]
+    );
+
+    // Pre-Depends and its dependencies are configured before unpacking the depender.
+    assert_eq!(
+      batched(
+        "Package: app\nArchitecture: amd64\nVersion: 1\nDepends: other\n\n\
+         Package: other\nArchitecture: amd64\nVersion: 1\n\n\
+         Package: lib\nArchitecture: amd64\nVersion: 1\nPre-Depends: base\n\n\
+         Package: base\nArchitecture: amd64\nVersion: 1\nDepends: core\n\n\
+         Package: core\nArchitecture: amd64\nVersion: 1\n"
+      ),
+      vec![
+        "UNPACK other app core base",
+        "CONFIGURE core base",
+        "UNPACK lib",
+        "CONFIGURE other app lib"
+      ]
+    );
+  }
+
+  #[test]
+  fn test_order_installation() {
+    use Action::*;
+    let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+    // dependencies come first, and Pre-Depends is configured before unpacking.
+    assert_eq!(
+      steps(
+        "Package: app\nArchitecture: amd64\nVersion: 1\nDepends: lib (>= 1), other\n\n\
+         Package: lib\nArchitecture: amd64\nVersion: 1\nPre-Depends: base\n\n\
+         Package: base\nArchitecture: amd64\nVersion: 1\n"
+      )
+      .unwrap(),
+      vec![
+        (UNPACK, names(&["base"])),
+        (CONFIGURE, names(&["base"])),
+        (UNPACK, names(&["lib"])),
+        (CONFIGURE, names(&["lib"])),
+        (UNPACK, names(&["app"])),
+        (CONFIGURE, names(&["app"])),
+      ]
+    );
+
+    // dependency cycle is unpacked at once before configured.
+    assert_eq!(
+      steps(
+        "Package: x\nArchitecture: amd64\nVersion: 1\nDepends: y\n\n\
+         Package: y\nArchitecture: amd64\nVersion: 1\nDepends: x | z\n"
+      )
+      .unwrap(),
+      vec![
+        (UNPACK, names(&["x", "y"])),
+        (CONFIGURE, names(&["x", "y"]))
+      ]
+    );
+
+    // Pre-Depends in a cycle is still honoured.
+    assert_eq!(
+      steps(
+        "Package: p\nArchitecture: amd64\nVersion: 1\nPre-Depends: q\n\n\
+         Package: q\nArchitecture: amd64\nVersion: 1\nDepends: r\n\n\
+         Package: r\nArchitecture: amd64\nVersion: 1\nDepends: p\n"
+      )
+      .unwrap(),
+      vec![
+        (UNPACK, names(&["q", "r"])),
+        (CONFIGURE, names(&["q"])),
+        (UNPACK, names(&["p"])),
+        (CONFIGURE, names(&["p", "r"])),
+      ]
+    );
+
+    // loop of Pre-Depends can't be ordered.
+    assert!(steps(
+      "Package: p\nArchitecture: amd64\nVersion: 1\nPre-Depends: q\n\n\
+       Package: q\nArchitecture: amd64\nVersion: 1\nPre-Depends: p\n"
+    )
+    .is_err());
+  }
+}
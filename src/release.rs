@@ -0,0 +1,283 @@
+use crate::chksum::{self, HashKind};
+
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct ReleaseFile {
+  pub path: String,
+  pub size: u64,
+  pub md5: Option<String>,
+  pub sha256: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct Release {
+  pub origin: String,
+  pub label: String,
+  pub suite: String,
+  pub codename: String,
+  pub date: String,
+  pub valid_until: String,
+  pub architectures: Vec<String>,
+  pub components: Vec<String>,
+  pub files: Vec<ReleaseFile>,
+}
+
+impl Release {
+  pub fn from_raw(raw: &str) -> Result<Self, String> {
+    let mut release = Release::default();
+    let mut cur_hash = None;
+
+    for line in raw.split("\n") {
+      if line.is_empty() {
+        continue;
+      }
+      if line.starts_with(' ') {
+        // entry of checksum section: [hash] [size] [path]
+        let kind = match cur_hash {
+          Some(_kind) => _kind,
+          None => continue, // continuation of a field we don't care about
+        };
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+        if parts.len() != 3 {
+          return Err(format!("invalid checksum entry in Release: {}", line));
+        }
+        let size = match parts[1].parse() {
+          Ok(_size) => _size,
+          Err(_) => return Err(format!("invalid size in Release: {}", line)),
+        };
+        let file = match release.files.iter_mut().find(|f| f.path == parts[2]) {
+          Some(_file) => _file,
+          None => {
+            release.files.push(ReleaseFile {
+              path: parts[2].to_string(),
+              size,
+              ..Default::default()
+            });
+            release.files.last_mut().unwrap()
+          }
+        };
+        match kind {
+          HashKind::MD5 => file.md5 = Some(parts[0].to_lowercase()),
+          HashKind::SHA1 => {} // too weak to be trusted
+          HashKind::SHA256 => file.sha256 = Some(parts[0].to_lowercase()),
+        }
+        continue;
+      }
+
+      cur_hash = None;
+      let parts = line.splitn(2, ":").collect::<Vec<_>>();
+      let value = match parts.get(1) {
+        Some(_value) => _value.trim().to_string(),
+        None => return Err(format!("invalid Release line: {}", line)),
+      };
+      match parts[0] {
+        "Origin" => release.origin = value,
+        "Label" => release.label = value,
+        "Suite" => release.suite = value,
+        "Codename" => release.codename = value,
+        "Date" => release.date = value,
+        "Valid-Until" => release.valid_until = value,
+        "Architectures" => {
+          release.architectures = value.split_whitespace().map(|s| s.to_string()).collect()
+        }
+        "Components" => {
+          release.components = value.split_whitespace().map(|s| s.to_string()).collect()
+        }
+        "MD5Sum" => cur_hash = Some(HashKind::MD5),
+        "SHA1" => cur_hash = Some(HashKind::SHA1),
+        "SHA256" => cur_hash = Some(HashKind::SHA256),
+        _ => {}
+      }
+    }
+
+    Ok(release)
+  }
+
+  pub fn find_file(&self, path: &str) -> Option<&ReleaseFile> {
+    self.files.iter().find(|f| f.path == path)
+  }
+
+  // check @data fetched as @path matches the Release entry.
+  // SHA256 is used if exists, and MD5Sum is used only as a fallback.
+  pub fn verify_file(&self, path: &str, data: &[u8]) -> Result<(), String> {
+    let file = match self.find_file(path) {
+      Some(_file) => _file,
+      None => return Err(format!("{} is not listed in Release file.", path)),
+    };
+    if file.size != data.len() as u64 {
+      return Err(format!(
+        "size mismatch of {}: expected {}, actual {}",
+        path,
+        file.size,
+        data.len()
+      ));
+    }
+    let (kind, expected) = match (&file.sha256, &file.md5) {
+      (Some(sha256), _) => (HashKind::SHA256, sha256),
+      (None, Some(md5)) => (HashKind::MD5, md5),
+      (None, None) => return Err(format!("{} has no checksum in Release file.", path)),
+    };
+    let actual = chksum::hash_hex(kind, data);
+    if &actual != expected {
+      return Err(format!(
+        "{} mismatch of {}: expected {}, actual {}",
+        kind, path, expected, actual
+      ));
+    }
+
+    Ok(())
+  }
+
+  // @ret: Err if Valid-Until of the Release is already past at @now(seconds since UNIX epoch).
+  pub fn check_valid_until(&self, now: u64) -> Result<(), String> {
+    if self.valid_until.is_empty() {
+      return Ok(());
+    }
+    let valid_until = parse_date(&self.valid_until)?;
+    if valid_until < now {
+      return Err(format!(
+        "Release file is expired (invalid since {}).",
+        self.valid_until
+      ));
+    }
+
+    Ok(())
+  }
+}
+
+// parse RFC 2822 style date used in Release files, such as 'Thu, 23 Apr 2020 17:33:17 UTC'.
+// @ret: seconds since UNIX epoch
+pub fn parse_date(date: &str) -> Result<u64, String> {
+  let invalid = || format!("invalid date in Release: {}", date);
+  let parts = date.split_whitespace().collect::<Vec<_>>();
+  // day of week is optional
+  let parts = if !parts.is_empty() && parts[0].ends_with(',') {
+    &parts[1..]
+  } else {
+    &parts[..]
+  };
+  if parts.len() < 4 {
+    return Err(invalid());
+  }
+  let day: u64 = parts[0].parse().map_err(|_| invalid())?;
+  let month = match [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+  ]
+  .iter()
+  .position(|m| *m == parts[1])
+  {
+    Some(_month) => _month as u64 + 1,
+    None => return Err(invalid()),
+  };
+  let year: u64 = parts[2].parse().map_err(|_| invalid())?;
+  let hms = parts[3]
+    .split(":")
+    .map(|n| n.parse::<u64>())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|_| invalid())?;
+  if hms.len() != 3 || year < 1970 {
+    return Err(invalid());
+  }
+  // only UTC(and +0000) is used in practice.
+  if let Some(zone) = parts.get(4) {
+    if *zone != "UTC" && *zone != "GMT" && *zone != "+0000" && *zone != "Z" {
+      return Err(invalid());
+    }
+  }
+
+  // days from 1970-01-01 (civil calendar)
+  let (y, m) = if month <= 2 {
+    (year - 1, month + 9)
+  } else {
+    (year, month - 3)
+  };
+  let era = y / 400;
+  let yoe = y - era * 400;
+  let doy = (153 * m + 2) / 5 + day - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  let days = era * 146097 + doe - 719468;
+
+  Ok(days * 86400 + hms[0] * 3600 + hms[1] * 60 + hms[2])
+}
+
+#[cfg(test)]
+pub mod test {
+  #[test]
+  fn test_release_from_raw() {
+    let raw = std::fs::read_to_string("test/repo/dists/focal/Release").unwrap();
+    let release = super::Release::from_raw(&raw).unwrap();
+    assert_eq!(release.suite, "focal");
+    assert_eq!(release.architectures, vec!["amd64".to_string()]);
+    assert_eq!(release.components, vec!["main".to_string()]);
+    assert_eq!(release.files.len(), 2);
+    let index = release.find_file("main/binary-amd64/Packages.gz").unwrap();
+    assert_eq!(index.size, 1608);
+    assert_eq!(
+      index.md5.as_ref().unwrap(),
+      "28f2c66a60a2d9618805366d2f781700"
+    );
+    assert_eq!(
+      index.sha256.as_ref().unwrap(),
+      "f79d19dabfa8060253a310b6543081be40fd152410e26685939551eba26f91b4"
+    );
+  }
+
+  #[test]
+  fn test_release_verify_file() {
+    let raw = std::fs::read_to_string("test/repo/dists/focal/Release").unwrap();
+    let mut release = super::Release::from_raw(&raw).unwrap();
+    let index = std::fs::read("test/repo/dists/focal/main/binary-amd64/Packages.gz").unwrap();
+    assert!(release
+      .verify_file("main/binary-amd64/Packages.gz", &index)
+      .is_ok());
+    assert!(release
+      .verify_file("restricted/binary-amd64/Packages.gz", &index)
+      .is_err());
+
+    let mut tampered = index.clone();
+    tampered[100] ^= 0xFF;
+    let msg = release
+      .verify_file("main/binary-amd64/Packages.gz", &tampered)
+      .unwrap_err();
+    assert!(msg.contains("SHA256 mismatch"));
+    assert!(msg.contains("f79d19dabfa8060253a310b6543081be40fd152410e26685939551eba26f91b4"));
+
+    // MD5Sum is used when SHA256 is missing.
+    release.files[1].sha256 = None;
+    assert!(release
+      .verify_file("main/binary-amd64/Packages.gz", &index)
+      .is_ok());
+    assert!(release
+      .verify_file("main/binary-amd64/Packages.gz", &tampered)
+      .unwrap_err()
+      .contains("MD5Sum mismatch"));
+  }
+
+  #[test]
+  fn test_parse_date() {
+    use super::parse_date;
+    assert_eq!(parse_date("Thu, 01 Jan 1970 00:00:00 UTC").unwrap(), 0);
+    assert_eq!(
+      parse_date("Thu, 23 Apr 2020 17:33:17 UTC").unwrap(),
+      1587663197
+    );
+    assert_eq!(
+      parse_date("29 Feb 2024 12:00:00 +0000").unwrap(),
+      1709208000
+    );
+    assert!(parse_date("Thu, 23 Foo 2020 17:33:17 UTC").is_err());
+    assert!(parse_date("Thu, 23 Apr 2020 17:33:17 JST").is_err());
+  }
+
+  #[test]
+  fn test_check_valid_until() {
+    let release = super::Release {
+      valid_until: "Thu, 23 Apr 2020 17:33:17 UTC".to_string(),
+      ..Default::default()
+    };
+    assert!(release.check_valid_until(1587663197).is_ok());
+    assert!(release.check_valid_until(1587663198).is_err());
+    assert!(super::Release::default()
+      .check_valid_until(u64::MAX)
+      .is_ok());
+  }
+}
@@ -0,0 +1,357 @@
+use crate::dpkg;
+use crate::source::{self, Priority, SourcePackage, StatusStatus};
+use colored::*;
+use std::io::Write;
+
+// remove(or purge with @purge) installed @packages('pkg' or 'pkg:arch') and packages
+// depending on them. essential packages are kept unless @allow_essential.
+pub fn do_remove(packages: &[String], purge: bool, allow_essential: bool) {
+  let installed = &*source::DPKG_CACHE;
+
+  let mut targets = vec![];
+  for package in packages {
+    let (name, arch) = source::split_qualified_name(package);
+    let founds = installed
+      .iter()
+      .filter(|i| {
+        i.package == name
+          && match arch {
+            Some(arch) => i.matches_arch(arch),
+            None => true,
+          }
+      })
+      .filter(|i| {
+        // only configuration files are left for removed packages.
+        i.status.status == StatusStatus::INSTALLED
+          || (purge && i.status.status == StatusStatus::CONFIG_FILES)
+      })
+      .collect::<Vec<_>>();
+    if founds.is_empty() {
+      println!(
+        "Package {} is not installed, so not removed.",
+        package.green()
+      );
+    }
+    targets.extend(founds.into_iter().cloned());
+  }
+  if targets.is_empty() {
+    return;
+  }
+
+  let removals = removal_closure(&targets, installed);
+  let essentials = removals.iter().filter(|r| r.essential).collect::<Vec<_>>();
+  if !essentials.is_empty() && !allow_essential {
+    println!(
+      "Refusing to remove essential packages: {}",
+      essentials
+        .iter()
+        .map(|e| e.qualified_name())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .red()
+        .bold()
+    );
+    println!("Use '--allow-remove-essential' if you really mean it.");
+    return;
+  }
+
+  let is_target = |p: &SourcePackage| {
+    targets
+      .iter()
+      .any(|t| t.arch_qualified_name() == p.arch_qualified_name())
+  };
+  let extras = removals
+    .iter()
+    .filter(|r| !is_target(r))
+    .collect::<Vec<_>>();
+  if !extras.is_empty() {
+    print!("The following packages depend on them, and will be also REMOVED: \n  ");
+    for ep in &extras {
+      print!("{} ", ep.qualified_name());
+    }
+    println!();
+  }
+  if !confirm_removal(&removals, purge, false) {
+    return;
+  }
+
+  match dpkg::remove_packages(&removals, purge, allow_essential) {
+    Ok(()) => println!("{}", "Remove complete.".yellow().bold()),
+    Err(msg) => println!("{}", msg),
+  }
+}
+
+// show @removals and ask whether to continue, or just show them with @dry_run.
+// @ret: true if the removal should be executed.
+fn confirm_removal(removals: &[SourcePackage], purge: bool, dry_run: bool) -> bool {
+  print!("The following packages will be REMOVED: \n  ");
+  for rp in removals {
+    let name = if purge {
+      format!("{}*", rp.qualified_name())
+    } else {
+      rp.qualified_name()
+    };
+    print!("{} ", name.red());
+  }
+  println!();
+  println!(
+    "0 upgraded, 0 newly installed, {} to remove.",
+    removals.len()
+  );
+
+  if dry_run {
+    return false;
+  }
+
+  print!("Do you want to continue? [Y/n] ");
+  std::io::stdout().flush().unwrap();
+  let mut user_yn = String::new();
+  std::io::stdin()
+    .read_line(&mut user_yn)
+    .expect("invalid input");
+  if user_yn != "y\n" && user_yn != "Y\n" {
+    println!("Abort.");
+    return false;
+  }
+
+  true
+}
+
+// remove packages which were installed automatically and are no longer needed.
+// only lists them with @dry_run.
+pub fn do_autoremove(dry_run: bool) {
+  let removals = unused_packages(&source::DPKG_CACHE, &source::EXTENDED_CACHE);
+  if removals.is_empty() {
+    println!("0 upgraded, 0 newly installed, 0 to remove.");
+    return;
+  }
+  println!("The following packages were automatically installed and are no longer required:");
+  if !confirm_removal(&removals, false, dry_run) {
+    return;
+  }
+
+  match dpkg::remove_packages(&removals, false, false) {
+    Ok(()) => println!("{}", "Remove complete.".yellow().bold()),
+    Err(msg) => println!("{}", msg),
+  }
+}
+
+// @ret: installed packages unreachable from manually installed, essential and required ones
+//       through Pre-Depends, Depends and Recommends.
+// @param extended: auto-installed flags of the packages.
+pub fn unused_packages(
+  installed: &[SourcePackage],
+  extended: &[source::ExtendedState],
+) -> Vec<SourcePackage> {
+  let installed = installed
+    .iter()
+    .filter(|i| i.status.status == StatusStatus::INSTALLED)
+    .collect::<Vec<_>>();
+  let is_auto = |p: &SourcePackage| source::auto_installed_in(extended, p);
+
+  let mut reached = installed
+    .iter()
+    .map(|i| !is_auto(i) || i.essential || i.priority == Priority::REQUIRED)
+    .collect::<Vec<_>>();
+  let mut queue = (0..installed.len())
+    .filter(|ix| reached[*ix])
+    .collect::<Vec<_>>();
+  while let Some(ix) = queue.pop() {
+    let package = installed[ix];
+    let arch = package.arch_name();
+    let relations = package
+      .pre_depends
+      .iter()
+      .chain(package.depends.iter())
+      .chain(package.recommends.iter())
+      .flatten()
+      .filter(|r| r.applies_to_arch(&arch));
+    for relation in relations {
+      // every installed alternative is kept, as any of them may be the one in use.
+      for (dix, dependency) in installed.iter().enumerate() {
+        if !reached[dix]
+          && dependency.satisfies_arch(relation.arch_qualifier.as_deref(), &arch)
+          && relation.satisfied_by(dependency)
+        {
+          reached[dix] = true;
+          queue.push(dix);
+        }
+      }
+    }
+  }
+
+  installed
+    .iter()
+    .zip(reached)
+    .filter(|(_, r)| !r)
+    .map(|(i, _)| (*i).clone())
+    .collect()
+}
+
+// @ret: @targets and installed packages whose Depends or Pre-Depends get broken
+//       without them, recursively.
+pub fn removal_closure(
+  targets: &[SourcePackage],
+  installed: &[SourcePackage],
+) -> Vec<SourcePackage> {
+  let key = |p: &SourcePackage| p.arch_qualified_name();
+  let mut removals = targets.to_vec();
+  loop {
+    let remainings = installed
+      .iter()
+      .filter(|i| i.status.status == StatusStatus::INSTALLED)
+      .filter(|i| !removals.iter().any(|r| key(r) == key(i)))
+      .collect::<Vec<_>>();
+    let broken = remainings
+      .iter()
+      .filter(|p| {
+        let arch = p.arch_name();
+        p.pre_depends.iter().chain(p.depends.iter()).any(|group| {
+          let relations = group
+            .iter()
+            .filter(|r| r.applies_to_arch(&arch))
+            .collect::<Vec<_>>();
+          // groups unrelated to the removals can't be broken by them.
+          let related = relations
+            .iter()
+            .any(|r| removals.iter().any(|rm| r.satisfied_by(rm)));
+          related
+            && !relations.iter().any(|r| {
+              remainings
+                .iter()
+                .any(|i| i.satisfies_arch(r.arch_qualifier.as_deref(), &arch) && r.satisfied_by(i))
+            })
+        })
+      })
+      .map(|p| (*p).clone())
+      .collect::<Vec<_>>();
+    if broken.is_empty() {
+      break;
+    }
+    removals.extend(broken);
+  }
+
+  removals
+}
+
+#[cfg(test)]
+pub mod test {
+  use crate::source::SourcePackage;
+
+  #[test]
+  fn test_removal_closure() {
+    let installed = SourcePackage::from_raw(
+      "Package: lib\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\n\
+       Package: app\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\
+       Depends: lib (>= 1)\n\n\
+       Package: plugin\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\
+       Pre-Depends: app\n\n\
+       Package: alt\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\
+       Depends: lib | mta\n\n\
+       Package: exim\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\
+       Provides: mta\n\n\
+       Package: old\nStatus: deinstall ok config-files\nArchitecture: amd64\nVersion: 1\n\
+       Depends: lib\n",
+      "",
+    )
+    .unwrap();
+    let names =
+      |packages: Vec<SourcePackage>| packages.into_iter().map(|p| p.package).collect::<Vec<_>>();
+
+    // reverse dependencies are removed recursively, unless another alternative remains.
+    assert_eq!(
+      names(super::removal_closure(&installed[0..1], &installed)),
+      vec!["lib", "app", "plugin"]
+    );
+    assert_eq!(
+      names(super::removal_closure(&installed[4..5], &installed)),
+      vec!["exim"]
+    );
+    let targets = vec![installed[0].clone(), installed[4].clone()];
+    assert_eq!(
+      names(super::removal_closure(&targets, &installed)),
+      vec!["lib", "exim", "app", "alt", "plugin"]
+    );
+  }
+
+  #[test]
+  fn test_unused_packages() {
+    let installed = SourcePackage::from_raw(
+      "Package: app\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\
+       Depends: lib | mta\nRecommends: doc\n\n\
+       Package: lib\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\n\
+       Package: exim\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\
+       Provides: mta\nPre-Depends: exim-base\n\n\
+       Package: exim-base\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\n\
+       Package: doc\nStatus: install ok installed\nArchitecture: all\nVersion: 1\n\n\
+       Package: orphan\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\n\
+       Package: base\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\
+       Priority: required\n\n\
+       Package: gone\nStatus: deinstall ok config-files\nArchitecture: amd64\nVersion: 1\n",
+      "",
+    )
+    .unwrap();
+    // extended_states records 'all' packages as native ones.
+    let native = crate::config::CONFIG.architecture.clone();
+    let auto = |names: &[&str]| {
+      installed
+        .iter()
+        .map(|i| {
+          (
+            i.package.clone(),
+            match i.arch_name().as_str() {
+              "all" => native.clone(),
+              _arch => _arch.to_string(),
+            },
+            names.contains(&i.package.as_str()),
+          )
+        })
+        .collect::<Vec<_>>()
+    };
+    let names =
+      |packages: Vec<SourcePackage>| packages.into_iter().map(|p| p.package).collect::<Vec<_>>();
+
+    let all = [
+      "app",
+      "lib",
+      "exim",
+      "exim-base",
+      "doc",
+      "orphan",
+      "base",
+      "gone",
+    ];
+    assert_eq!(
+      names(super::unused_packages(&installed, &auto(&all[1..]))),
+      vec!["orphan"]
+    );
+    assert_eq!(
+      names(super::unused_packages(&installed, &auto(&all))),
+      vec!["app", "lib", "exim", "exim-base", "doc", "orphan"]
+    );
+    // packages without the record are manual.
+    assert!(super::unused_packages(&installed, &[]).is_empty());
+
+    // marks of the same package on different architectures are independent.
+    let multiarch = SourcePackage::from_raw(
+      "Package: foo\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\
+       Multi-Arch: same\n\n\
+       Package: foo\nStatus: install ok installed\nArchitecture: i386\nVersion: 1\n\
+       Multi-Arch: same\n",
+      "",
+    )
+    .unwrap();
+    let extended = vec![
+      ("foo".to_string(), "amd64".to_string(), true),
+      ("foo".to_string(), "i386".to_string(), false),
+    ];
+    assert_eq!(
+      super::unused_packages(&multiarch, &extended)
+        .iter()
+        .map(|p| p.qualified_name())
+        .collect::<Vec<_>>(),
+      vec![multiarch[0].qualified_name()]
+    );
+    assert_eq!(super::unused_packages(&multiarch[1..], &extended).len(), 0);
+  }
+}
@@ -0,0 +1,1023 @@
+use crate::dpkg::{self, DependencyFlags};
+use crate::source::{self, MultiArch, OrGroup, Relation, SourcePackage, StatusStatus};
+use crate::version::comp_version;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+
+// the search is given up after this number of tried choices.
+const MAX_STEPS: usize = 100000;
+
+// consistent set of changes to installed packages.
+#[derive(Debug, PartialEq, Default)]
+pub struct Solution {
+  // packages which are not installed yet.
+  pub installs: Vec<SourcePackage>,
+  // installed packages which are replaced with other versions.
+  pub upgrades: Vec<SourcePackage>,
+  pub removals: Vec<SourcePackage>,
+}
+
+// restrictions on solutions, as 'Forbid-New-Install' and 'Forbid-Remove' of EDSP.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Constraints {
+  pub forbid_new_install: bool,
+  pub forbid_remove: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Decision {
+  // index of the package in the cache
+  Install(usize),
+  Remove,
+}
+
+#[derive(Debug, Clone)]
+enum Goal {
+  // @group of @depender('pkg:arch') of @version must be satisfied.
+  // optional one(Recommends, Suggests) is dropped if it can't be.
+  Depends {
+    depender: String,
+    version: String,
+    group: OrGroup,
+    required: bool,
+  },
+  // installed @package('pkg:arch') must get along with packages to be installed.
+  Conflict {
+    package: String,
+  },
+}
+
+#[derive(Debug, Clone)]
+struct Task {
+  goal: Goal,
+  // relations which lead from the request to the goal
+  chain: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Choice {
+  Select(usize),
+  Remove(String),
+}
+
+#[derive(Debug, Default)]
+struct State {
+  // keyed by 'pkg:arch'. installed packages without decision are kept as they are.
+  decisions: HashMap<String, Decision>,
+  // keys of @decisions in order of decision
+  order: Vec<String>,
+  agenda: VecDeque<Task>,
+  // changes to be undone in reverse order on backtracking.
+  trail: Vec<Change>,
+}
+
+#[derive(Debug)]
+enum Change {
+  Decide,
+  Push,
+  Pop(Task),
+}
+
+impl State {
+  fn decide(&mut self, key: String, decision: Decision) {
+    self.decisions.insert(key.clone(), decision);
+    self.order.push(key);
+    self.trail.push(Change::Decide);
+  }
+
+  fn push(&mut self, task: Task) {
+    self.agenda.push_back(task);
+    self.trail.push(Change::Push);
+  }
+
+  fn pop(&mut self) -> Option<Task> {
+    let task = self.agenda.pop_front()?;
+    self.trail.push(Change::Pop(task.clone()));
+    Some(task)
+  }
+
+  // restore the state when @trail was @mark long.
+  fn undo(&mut self, mark: usize) {
+    while self.trail.len() > mark {
+      match self.trail.pop().unwrap() {
+        Change::Decide => {
+          let key = self.order.pop().unwrap();
+          self.decisions.remove(&key);
+        }
+        Change::Push => {
+          self.agenda.pop_back();
+        }
+        Change::Pop(task) => self.agenda.push_front(task),
+      }
+    }
+  }
+}
+
+// task which can be fulfilled in several ways, to be tried one by one.
+struct Branch {
+  task: Task,
+  choices: Vec<Choice>,
+  // index of the choice to be tried next
+  next: usize,
+  required: bool,
+  // length of the trail before the choices are applied
+  mark: usize,
+  // the first reason why a choice failed
+  failure: Option<Failure>,
+}
+
+// why resolution failed, with the chain of relations from the request.
+#[derive(Debug)]
+struct Failure {
+  chain: Vec<String>,
+  reason: String,
+  // whether the search is given up as a whole.
+  exhausted: bool,
+}
+
+impl Failure {
+  fn new(chain: &[String], reason: String) -> Self {
+    Self {
+      chain: chain.to_vec(),
+      reason,
+      exhausted: false,
+    }
+  }
+
+  fn to_message(&self) -> String {
+    let mut lines = vec!["Unable to resolve dependencies:".to_string()];
+    lines.extend(self.chain.iter().map(|c| format!("  {}", c)));
+    lines.push(format!("  {}", self.reason));
+    lines.join("\n")
+  }
+}
+
+struct Resolver<'a> {
+  installed: &'a [SourcePackage],
+  cache: &'a [SourcePackage],
+  flags: DependencyFlags,
+  // 'pkg:arch' of requested packages, which are never removed.
+  requested: Vec<String>,
+  installed_keys: HashMap<String, usize>,
+  installed_names: HashMap<String, Vec<usize>>,
+  installed_providers: source::ProviderIndex,
+  cache_names: HashMap<String, Vec<usize>>,
+  cache_providers: source::ProviderIndex,
+  constraints: Constraints,
+  steps: Cell<usize>,
+}
+
+// find changes to @installed packages which install @requests consistently.
+// dependencies are searched in @cache, trying alternatives and backtracking from
+// bad choices. already installed packages and higher versions are preferred.
+// @installed: dpkg status. only packages in 'installed' status are considered.
+// @requests: packages in @cache to be installed.
+pub fn resolve(
+  requests: &[SourcePackage],
+  installed: &[SourcePackage],
+  cache: &[SourcePackage],
+  flags: DependencyFlags,
+) -> Result<Solution, String> {
+  let installed = installed_only(installed);
+  let resolver = Resolver::new(requests, &installed, cache, flags, Constraints::default());
+
+  let mut state = State::default();
+  for request in requests {
+    let chain = vec![format!("{} is requested.", request.qualified_name())];
+    let ix = resolver.find_request(request, &chain)?;
+    if let Err(failure) = resolver.select(&mut state, ix, &chain) {
+      return Err(failure.to_message());
+    }
+  }
+  if let Err(failure) = resolver.solve(&mut state) {
+    return Err(failure.to_message());
+  }
+
+  Ok(resolver.solution(&state))
+}
+
+// upgrade installed packages to @upgrades as many as possible within @constraints.
+// each upgrade is tried in turn on top of the accepted ones, and just skipped if impossible.
+// @ret: changes for the accepted upgrades.
+pub fn upgrade(
+  upgrades: &[SourcePackage],
+  installed: &[SourcePackage],
+  cache: &[SourcePackage],
+  flags: DependencyFlags,
+  constraints: Constraints,
+) -> Result<Solution, String> {
+  let installed = installed_only(installed);
+  let resolver = Resolver::new(upgrades, &installed, cache, flags, constraints);
+
+  let mut state = State::default();
+  for upgrade in upgrades {
+    let chain = vec![format!("{} is requested.", upgrade.qualified_name())];
+    let ix = resolver.find_request(upgrade, &chain)?;
+    let mark = state.trail.len();
+    match resolver
+      .select(&mut state, ix, &chain)
+      .and_then(|_| resolver.solve(&mut state))
+    {
+      // accepted upgrades are never undone.
+      Ok(()) => state.trail.clear(),
+      Err(failure) if failure.exhausted => return Err(failure.to_message()),
+      Err(failure) => {
+        log::info!("not upgrading: {}", failure.to_message());
+        state.undo(mark);
+      }
+    }
+  }
+
+  Ok(resolver.solution(&state))
+}
+
+fn installed_only(installed: &[SourcePackage]) -> Vec<SourcePackage> {
+  installed
+    .iter()
+    .filter(|i| i.status.status == StatusStatus::INSTALLED)
+    .cloned()
+    .collect()
+}
+
+impl<'a> Resolver<'a> {
+  fn new(
+    requests: &[SourcePackage],
+    installed: &'a [SourcePackage],
+    cache: &'a [SourcePackage],
+    flags: DependencyFlags,
+    constraints: Constraints,
+  ) -> Self {
+    let name_index = |items: &[SourcePackage]| {
+      let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+      for (ix, item) in items.iter().enumerate() {
+        index.entry(item.package.clone()).or_default().push(ix);
+      }
+      index
+    };
+    Self {
+      installed,
+      cache,
+      flags,
+      requested: requests.iter().map(|r| r.arch_qualified_name()).collect(),
+      installed_keys: installed
+        .iter()
+        .enumerate()
+        .map(|(ix, i)| (i.arch_qualified_name(), ix))
+        .collect(),
+      installed_names: name_index(installed),
+      installed_providers: source::build_provider_index(installed),
+      cache_names: name_index(cache),
+      cache_providers: source::build_provider_index(cache),
+      constraints,
+      steps: Cell::new(0),
+    }
+  }
+
+  // @ret: index of @request in the cache.
+  fn find_request(&self, request: &SourcePackage, chain: &[String]) -> Result<usize, String> {
+    match self.cache.iter().position(|c| {
+      c.arch_qualified_name() == request.arch_qualified_name() && c.version == request.version
+    }) {
+      Some(_ix) => Ok(_ix),
+      None => Err(Failure::new(chain, "it is not in cache files.".to_string()).to_message()),
+    }
+  }
+
+  fn solution(&self, state: &State) -> Solution {
+    let mut solution = Solution::default();
+    for key in &state.order {
+      match state.decisions[key] {
+        Decision::Install(ix) if self.installed_keys.contains_key(key) => {
+          solution.upgrades.push(self.cache[ix].clone())
+        }
+        Decision::Install(ix) => solution.installs.push(self.cache[ix].clone()),
+        Decision::Remove => solution
+          .removals
+          .push(self.installed[self.installed_keys[key]].clone()),
+      }
+    }
+    solution
+  }
+
+  fn solve(&self, state: &mut State) -> Result<(), Failure> {
+    let mut branches = vec![];
+    while let Some(task) = state.pop() {
+      let choices = match self.choices(state, &task) {
+        Some(_choices) => _choices,
+        None => continue,
+      };
+      let required = !matches!(
+        task.goal,
+        Goal::Depends {
+          required: false,
+          ..
+        }
+      );
+
+      // no need to branch if there is only one way.
+      if required && choices.len() == 1 {
+        self.tick(&task.chain)?;
+        if let Err(failure) = self.apply(state, &choices[0], &task.chain) {
+          self.backtrack(state, &mut branches, Some(failure))?;
+        }
+        continue;
+      }
+      branches.push(Branch {
+        task,
+        choices,
+        next: 0,
+        required,
+        mark: state.trail.len(),
+        failure: None,
+      });
+      self.backtrack(state, &mut branches, None)?;
+    }
+
+    Ok(())
+  }
+
+  // go back to the latest branch which has choices not tried yet, and apply the next one.
+  // @failure: why the current way failed, if it did.
+  fn backtrack(
+    &self,
+    state: &mut State,
+    branches: &mut Vec<Branch>,
+    mut failure: Option<Failure>,
+  ) -> Result<(), Failure> {
+    loop {
+      if let Some(_failure) = failure.take() {
+        if _failure.exhausted || branches.is_empty() {
+          return Err(_failure);
+        }
+        branches.last_mut().unwrap().failure.get_or_insert(_failure);
+      }
+      let branch = branches.last_mut().unwrap();
+      state.undo(branch.mark);
+      if branch.next < branch.choices.len() {
+        branch.next += 1;
+        self.tick(&branch.task.chain)?;
+        match self.apply(state, &branch.choices[branch.next - 1], &branch.task.chain) {
+          Ok(()) => return Ok(()),
+          Err(_failure) => failure = Some(_failure),
+        }
+        continue;
+      }
+
+      let branch = branches.pop().unwrap();
+      if !branch.required {
+        // optional dependency is just dropped.
+        log::info!(
+          "dropping optional dependency: {}",
+          branch.task.chain.join(" ")
+        );
+        return Ok(());
+      }
+      failure = Some(match branch.failure {
+        Some(_failure) => _failure,
+        None => {
+          let reason = match &branch.task.goal {
+            Goal::Depends { group, .. } => format!(
+              "{} is not available.",
+              source::format_depends(std::slice::from_ref(group))
+            ),
+            Goal::Conflict { package } => format!("{} can't be upgraded or removed.", package),
+          };
+          Failure::new(&branch.task.chain, reason)
+        }
+      });
+    }
+  }
+
+  fn tick(&self, chain: &[String]) -> Result<(), Failure> {
+    self.steps.set(self.steps.get() + 1);
+    if self.steps.get() > MAX_STEPS {
+      return Err(Failure {
+        chain: chain.to_vec(),
+        reason: "too many choices to be tried. giving up.".to_string(),
+        exhausted: true,
+      });
+    }
+    Ok(())
+  }
+
+  fn apply(&self, state: &mut State, choice: &Choice, chain: &[String]) -> Result<(), Failure> {
+    match choice {
+      Choice::Select(ix) => self.select(state, *ix, chain),
+      Choice::Remove(key) => self.remove(state, key, chain),
+    }
+  }
+
+  // @ret: changes which may fulfil @task in order of preference, or None if already fulfilled.
+  fn choices(&self, state: &State, task: &Task) -> Option<Vec<Choice>> {
+    match &task.goal {
+      Goal::Depends {
+        depender,
+        version,
+        group,
+        ..
+      } => {
+        // depender may be removed or replaced meanwhile.
+        let depender_package = match self.present(state, depender) {
+          Some(_package) if &_package.version == version => _package,
+          _ => return None,
+        };
+        let arch = depender_package.arch_name();
+        let relations = group
+          .iter()
+          .filter(|r| r.applies_to_arch(&arch))
+          .collect::<Vec<_>>();
+        if relations.is_empty() || self.satisfied(state, &relations, &arch) {
+          return None;
+        }
+
+        let mut choices = self
+          .candidates(&relations, &arch)
+          .into_iter()
+          .map(Choice::Select)
+          .collect::<Vec<_>>();
+        // installed package broken by the plan may be upgraded, or removed as a last resort.
+        if !state.decisions.contains_key(depender) {
+          choices.extend(self.upgrades_of(depender).into_iter().map(Choice::Select));
+          choices.push(Choice::Remove(depender.clone()));
+        }
+        Some(choices)
+      }
+      Goal::Conflict { package } => {
+        let installed = match (state.decisions.get(package), self.present(state, package)) {
+          (None, Some(_installed)) => _installed,
+          _ => return None,
+        };
+        if !self
+          .planned(state)
+          .any(|p| dpkg::conflicts_with(p, installed) || dpkg::conflicts_with(installed, p))
+        {
+          return None;
+        }
+
+        let mut choices = self
+          .upgrades_of(package)
+          .into_iter()
+          .map(Choice::Select)
+          .collect::<Vec<_>>();
+        // package replaced by the plan is removed rather than upgraded.
+        if self.planned(state).any(|p| dpkg::replaces(p, installed)) {
+          choices.insert(0, Choice::Remove(package.clone()));
+        } else {
+          choices.push(Choice::Remove(package.clone()));
+        }
+        Some(choices)
+      }
+    }
+  }
+
+  // plan to install @ix of the cache, and push goals which it brings.
+  fn select(&self, state: &mut State, ix: usize, chain: &[String]) -> Result<(), Failure> {
+    let package = &self.cache[ix];
+    let key = package.arch_qualified_name();
+    match state.decisions.get(&key) {
+      Some(Decision::Install(other)) if self.cache[*other].version == package.version => {
+        return Ok(())
+      }
+      Some(Decision::Install(other)) => {
+        return Err(Failure::new(
+          chain,
+          format!(
+            "{} {} is needed, but {} is planned to be installed.",
+            package.qualified_name(),
+            package.version,
+            self.cache[*other].version
+          ),
+        ))
+      }
+      Some(Decision::Remove) => {
+        return Err(Failure::new(
+          chain,
+          format!(
+            "{} is needed, but planned to be removed.",
+            package.qualified_name()
+          ),
+        ))
+      }
+      None => {}
+    }
+    let installed = self.installed_keys.get(&key).map(|ix| &self.installed[*ix]);
+    if installed.map(|i| &i.version) == Some(&package.version) {
+      return Ok(());
+    }
+    if installed.is_none() && self.constraints.forbid_new_install {
+      return Err(Failure::new(
+        chain,
+        format!(
+          "{} {} would have to be newly installed.",
+          package.qualified_name(),
+          package.version
+        ),
+      ));
+    }
+    if let Some(held) = installed.filter(|i| i.is_held()) {
+      return Err(Failure::new(
+        chain,
+        format!(
+          "{} {} is needed, but {} is held.",
+          package.qualified_name(),
+          package.version,
+          held.version
+        ),
+      ));
+    }
+
+    // only 'Multi-Arch: same' packages can be installed for several architectures at once.
+    let arch = package.arch_name();
+    for other in self.present_named(state, &package.package) {
+      let other_arch = other.arch_name();
+      if other.package != package.package
+        || other_arch == arch
+        || other_arch == "all"
+        || arch == "all"
+      {
+        continue;
+      }
+      if package.multi_arch != MultiArch::SAME || other.multi_arch != MultiArch::SAME {
+        return Err(Failure::new(
+          chain,
+          format!(
+            "{} is not co-installable with {}.",
+            package.arch_qualified_name(),
+            other.arch_qualified_name()
+          ),
+        ));
+      }
+    }
+
+    // conflicts in the plan are fatal, while installed packages can be dealt with later.
+    let mut broken = vec![];
+    for other in self.present_all(state) {
+      let other_key = other.arch_qualified_name();
+      if other_key == key
+        || (!dpkg::conflicts_with(package, other) && !dpkg::conflicts_with(other, package))
+      {
+        continue;
+      }
+      if state.decisions.contains_key(&other_key) {
+        return Err(Failure::new(
+          chain,
+          format!(
+            "{} {} can't be installed together with {} {}.",
+            package.qualified_name(),
+            package.version,
+            other.qualified_name(),
+            other.version
+          ),
+        ));
+      }
+      broken.push(other_key);
+    }
+
+    state.decide(key.clone(), Decision::Install(ix));
+
+    let optional = |enabled: bool, groups: &'a [OrGroup]| if enabled { groups } else { &[] };
+    let fields: [(&str, &[OrGroup], bool); 4] = [
+      ("pre-depends on", &package.pre_depends, true),
+      ("depends on", &package.depends, true),
+      (
+        "recommends",
+        optional(self.flags.recommends, &package.recommends),
+        false,
+      ),
+      (
+        "suggests",
+        optional(self.flags.suggests, &package.suggests),
+        false,
+      ),
+    ];
+    for (field, groups, required) in fields {
+      for group in groups {
+        state.push(Task {
+          goal: Goal::Depends {
+            depender: key.clone(),
+            version: package.version.clone(),
+            group: group.clone(),
+            required,
+          },
+          chain: with_link(
+            chain,
+            format!(
+              "{} {} {}",
+              package.qualified_name(),
+              field,
+              source::format_depends(std::slice::from_ref(group))
+            ),
+          ),
+        });
+      }
+    }
+    for other_key in broken {
+      state.push(Task {
+        chain: with_link(
+          chain,
+          format!("{} conflicts with {}", package.qualified_name(), other_key),
+        ),
+        goal: Goal::Conflict { package: other_key },
+      });
+    }
+    // packages depending on the installed version may be broken by the upgrade.
+    if let Some(installed) = installed {
+      self.push_reverse_depends(state, installed, chain);
+    }
+
+    Ok(())
+  }
+
+  // plan to remove installed @key.
+  fn remove(&self, state: &mut State, key: &str, chain: &[String]) -> Result<(), Failure> {
+    let package = match self.installed_keys.get(key) {
+      Some(ix) if !state.decisions.contains_key(key) => &self.installed[*ix],
+      _ => return Err(Failure::new(chain, format!("{} can't be removed.", key))),
+    };
+    if self.requested.iter().any(|r| r == key) {
+      return Err(Failure::new(
+        chain,
+        format!(
+          "{} is requested, but has to be removed.",
+          package.qualified_name()
+        ),
+      ));
+    }
+    if package.essential {
+      return Err(Failure::new(
+        chain,
+        format!(
+          "essential package {} would have to be removed.",
+          package.qualified_name()
+        ),
+      ));
+    }
+    if package.is_held() {
+      return Err(Failure::new(
+        chain,
+        format!(
+          "held package {} would have to be removed.",
+          package.qualified_name()
+        ),
+      ));
+    }
+    if self.constraints.forbid_remove {
+      return Err(Failure::new(
+        chain,
+        format!("{} would have to be removed.", package.qualified_name()),
+      ));
+    }
+
+    state.decide(key.to_string(), Decision::Remove);
+    self.push_reverse_depends(state, package, chain);
+
+    Ok(())
+  }
+
+  // re-check dependencies on @old of remaining packages, since it goes away.
+  fn push_reverse_depends(&self, state: &mut State, old: &SourcePackage, chain: &[String]) {
+    let names = std::iter::once(&old.package)
+      .chain(old.provides.iter().map(|p| &p.name))
+      .collect::<Vec<_>>();
+    let mut tasks = vec![];
+    for package in self.present_all(state) {
+      for group in package.pre_depends.iter().chain(package.depends.iter()) {
+        if group.iter().any(|r| names.contains(&&r.name)) {
+          tasks.push(Task {
+            goal: Goal::Depends {
+              depender: package.arch_qualified_name(),
+              version: package.version.clone(),
+              group: group.clone(),
+              required: true,
+            },
+            chain: with_link(
+              chain,
+              format!(
+                "{} depends on {}",
+                package.qualified_name(),
+                source::format_depends(std::slice::from_ref(group))
+              ),
+            ),
+          });
+        }
+      }
+    }
+    for task in tasks {
+      state.push(task);
+    }
+  }
+
+  // @ret: package of @key which remains or will be installed.
+  fn present(&self, state: &State, key: &str) -> Option<&'a SourcePackage> {
+    match state.decisions.get(key) {
+      Some(Decision::Install(ix)) => Some(&self.cache[*ix]),
+      Some(Decision::Remove) => None,
+      None => self.installed_keys.get(key).map(|ix| &self.installed[*ix]),
+    }
+  }
+
+  // @ret: packages to be installed or upgraded.
+  fn planned<'s>(&'s self, state: &'s State) -> impl Iterator<Item = &'a SourcePackage> + 's {
+    let cache = self.cache;
+    state.decisions.values().filter_map(move |d| match d {
+      Decision::Install(ix) => Some(&cache[*ix]),
+      Decision::Remove => None,
+    })
+  }
+
+  // @ret: every package which remains or will be installed.
+  fn present_all(&self, state: &State) -> Vec<&'a SourcePackage> {
+    self
+      .installed
+      .iter()
+      .filter(|i| !state.decisions.contains_key(&i.arch_qualified_name()))
+      .chain(self.planned(state))
+      .collect()
+  }
+
+  // @ret: packages which remain or will be installed, named or providing @name.
+  fn present_named(&self, state: &State, name: &str) -> Vec<&'a SourcePackage> {
+    let installed = self
+      .installed_names
+      .get(name)
+      .into_iter()
+      .chain(self.installed_providers.get(name))
+      .flatten()
+      .map(|ix| &self.installed[*ix])
+      .filter(|i| !state.decisions.contains_key(&i.arch_qualified_name()));
+    let planned = self
+      .planned(state)
+      .filter(|p| p.package == name || p.provides.iter().any(|pr| pr.name == name));
+    installed.chain(planned).collect()
+  }
+
+  fn satisfied(&self, state: &State, relations: &[&Relation], arch: &str) -> bool {
+    relations.iter().any(|r| {
+      self
+        .present_named(state, &r.name)
+        .iter()
+        .any(|p| p.satisfies_arch(r.arch_qualifier.as_deref(), arch) && r.satisfied_by(p))
+    })
+  }
+
+  // @ret: indices of cache packages which satisfy some of @relations from a package of @arch.
+  //       earlier alternatives come first, and then installed packages, real packages,
+  //       ones of @arch and higher versions.
+  fn candidates(&self, relations: &[&Relation], arch: &str) -> Vec<usize> {
+    let mut ret: Vec<usize> = vec![];
+    for relation in relations {
+      let mut founds = self
+        .cache_names
+        .get(&relation.name)
+        .into_iter()
+        .chain(self.cache_providers.get(&relation.name))
+        .flatten()
+        .copied()
+        .filter(|ix| {
+          let p = &self.cache[*ix];
+          p.satisfies_arch(relation.arch_qualifier.as_deref(), arch) && relation.satisfied_by(p)
+        })
+        .collect::<Vec<_>>();
+      let rank = |p: &SourcePackage| {
+        (
+          !self.installed_keys.contains_key(&p.arch_qualified_name()),
+          p.package != relation.name,
+          p.arch_name() != arch,
+        )
+      };
+      founds.sort_by(|a, b| {
+        let (a, b) = (&self.cache[*a], &self.cache[*b]);
+        rank(a)
+          .cmp(&rank(b))
+          .then(comp_version(&b.version, &a.version).cmp(&0))
+      });
+      for ix in founds {
+        if !ret.contains(&ix) {
+          ret.push(ix);
+        }
+      }
+    }
+    ret
+  }
+
+  // @ret: indices of cache packages newer than installed @key, higher version first.
+  //       held package is never upgraded.
+  fn upgrades_of(&self, key: &str) -> Vec<usize> {
+    let installed = match self.installed_keys.get(key) {
+      Some(ix) if !self.installed[*ix].is_held() => &self.installed[*ix],
+      _ => return vec![],
+    };
+    let mut upgrades = self
+      .cache_names
+      .get(&installed.package)
+      .into_iter()
+      .flatten()
+      .copied()
+      .filter(|ix| {
+        let c = &self.cache[*ix];
+        c.arch_qualified_name() == key && comp_version(&c.version, &installed.version) > 0
+      })
+      .collect::<Vec<_>>();
+    upgrades.sort_by(|a, b| comp_version(&self.cache[*b].version, &self.cache[*a].version).cmp(&0));
+    upgrades
+  }
+}
+
+fn with_link(chain: &[String], link: String) -> Vec<String> {
+  let mut chain = chain.to_vec();
+  chain.push(link);
+  chain
+}
+
+#[cfg(test)]
+pub mod test {
+  use super::{resolve, Constraints};
+  use crate::dpkg::DependencyFlags;
+  use crate::source::SourcePackage;
+
+  fn parse(raw: &str) -> Vec<SourcePackage> {
+    SourcePackage::from_raw(raw, "").unwrap()
+  }
+
+  fn names(packages: &[SourcePackage]) -> Vec<String> {
+    packages
+      .iter()
+      .map(|p| format!("{}={}", p.package, p.version))
+      .collect()
+  }
+
+  #[test]
+  fn test_resolve_alternatives() {
+    let installed = parse(
+      "Package: libc6\nStatus: install ok installed\nArchitecture: amd64\nVersion: 2.31\n\
+       Essential: yes\n\n",
+    );
+    let cache = parse(
+      "Package: app\nArchitecture: amd64\nVersion: 1\nDepends: liba | libb, libc6 (>= 2.0)\n\n\
+       Package: liba\nArchitecture: amd64\nVersion: 1\nDepends: missing\n\n\
+       Package: libb\nArchitecture: amd64\nVersion: 1\nRecommends: extra\n\n\
+       Package: extra\nArchitecture: amd64\nVersion: 1\nDepends: nowhere\n\n\
+       Package: libc6\nArchitecture: amd64\nVersion: 2.31\n\n",
+    );
+    let flags = DependencyFlags::default();
+
+    // liba can't be installed, so the solver backs out of it and takes libb.
+    // unavailable Recommends is just dropped.
+    let solution = resolve(&cache[0..1], &installed, &cache, flags).unwrap();
+    assert_eq!(names(&solution.installs), vec!["app=1", "libb=1"]);
+    assert!(solution.upgrades.is_empty() && solution.removals.is_empty());
+
+    // the chain of relations is explained when no alternative is possible.
+    let msg = resolve(&cache[1..2], &installed, &cache, flags).unwrap_err();
+    assert!(msg.contains("liba depends on missing"));
+    assert!(msg.contains("missing is not available."));
+  }
+
+  #[test]
+  fn test_resolve_conflicts() {
+    let installed = parse(
+      "Package: oldlib\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1.0\n\n\
+       Package: mta\nStatus: install ok installed\nArchitecture: amd64\nVersion: 2.0\n\
+       Provides: mail-transport-agent\n\n\
+       Package: mailer\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1.0\n\
+       Depends: mail-transport-agent\n\n\
+       Package: libc6\nStatus: install ok installed\nArchitecture: amd64\nVersion: 2.31\n\
+       Essential: yes\n\n\
+       Package: removed\nStatus: deinstall ok config-files\nArchitecture: amd64\nVersion: 1\n\n",
+    );
+    let cache = parse(
+      "Package: oldlib\nArchitecture: amd64\nVersion: 1.1\n\n\
+       Package: newapp\nArchitecture: amd64\nVersion: 1\nBreaks: oldlib (<< 1.1)\n\n\
+       Package: newmta\nArchitecture: amd64\nVersion: 1\nConflicts: mail-transport-agent\n\
+       Replaces: mta\nProvides: mail-transport-agent\n\n\
+       Package: evil\nArchitecture: amd64\nVersion: 1\nConflicts: libc6\n\n\
+       Package: a\nArchitecture: amd64\nVersion: 1\nDepends: b\nConflicts: b\n\n\
+       Package: b\nArchitecture: amd64\nVersion: 1\n\n\
+       Package: lonely\nArchitecture: amd64\nVersion: 1\nConflicts: mail-transport-agent\n\n",
+    );
+    let flags = DependencyFlags::default();
+    let find = |name: &str| {
+      cache
+        .iter()
+        .filter(|c| c.package == name)
+        .cloned()
+        .collect::<Vec<_>>()
+    };
+
+    // broken package is upgraded if possible.
+    let solution = resolve(&find("newapp"), &installed, &cache, flags).unwrap();
+    assert_eq!(names(&solution.installs), vec!["newapp=1"]);
+    assert_eq!(names(&solution.upgrades), vec!["oldlib=1.1"]);
+    assert!(solution.removals.is_empty());
+
+    // conflicting package is removed otherwise, even via Provides.
+    // its reverse dependency is still satisfied by the new provider.
+    let solution = resolve(&find("newmta"), &installed, &cache, flags).unwrap();
+    assert_eq!(names(&solution.removals), vec!["mta=2.0"]);
+
+    // removal is cascaded to broken reverse dependencies.
+    let solution = resolve(&find("lonely"), &installed, &cache, flags).unwrap();
+    assert_eq!(names(&solution.removals), vec!["mta=2.0", "mailer=1.0"]);
+
+    // essential package is never removed, and conflicts in the plan are refused.
+    let msg = resolve(&find("evil"), &installed, &cache, flags).unwrap_err();
+    assert!(msg.contains("essential package libc6"));
+    let msg = resolve(&find("a"), &installed, &cache, flags).unwrap_err();
+    assert!(msg.contains("a depends on b"));
+    assert!(msg.contains("b 1 can't be installed together with a 1."));
+
+    // package taken over by 'Conflicts' and 'Replaces' is removed even if it can be upgraded,
+    // while files moved by 'Breaks' and 'Replaces' only need an upgrade.
+    let replaced =
+      parse("Package: old\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1.0\n");
+    let replacing = parse(
+      "Package: old\nArchitecture: amd64\nVersion: 1.1\n\n\
+       Package: renamed\nArchitecture: amd64\nVersion: 1\nConflicts: old (<< 1.1)\n\
+       Replaces: old (<< 1.1)\n\n\
+       Package: split\nArchitecture: amd64\nVersion: 1\nBreaks: old (<< 1.1)\n\
+       Replaces: old (<< 1.1)\n",
+    );
+    let solution = resolve(&replacing[1..2], &replaced, &replacing, flags).unwrap();
+    assert_eq!(names(&solution.removals), vec!["old=1.0"]);
+    assert!(solution.upgrades.is_empty());
+    let solution = resolve(&replacing[2..3], &replaced, &replacing, flags).unwrap();
+    assert_eq!(names(&solution.upgrades), vec!["old=1.1"]);
+    assert!(solution.removals.is_empty());
+
+    // held package is kept at its version.
+    let held = installed
+      .iter()
+      .cloned()
+      .map(|mut i| {
+        i.status.want = crate::source::StatusWant::HOLD;
+        i
+      })
+      .collect::<Vec<_>>();
+    let msg = resolve(&find("newapp"), &held, &cache, flags).unwrap_err();
+    assert!(msg.contains("held package oldlib would have to be removed."));
+    let msg = resolve(&find("oldlib"), &held, &cache, flags).unwrap_err();
+    assert!(msg.contains("oldlib 1.1 is needed, but 1.0 is held."));
+  }
+
+  #[test]
+  fn test_upgrade() {
+    let installed = parse(
+      "Package: a\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\n\
+       Package: b\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\n\
+       Package: c\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\n\
+       Package: e\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\n\
+       Package: f\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1\n\n",
+    );
+    let cache = parse(
+      "Package: a\nArchitecture: amd64\nVersion: 2\nDepends: newdep\n\n\
+       Package: newdep\nArchitecture: amd64\nVersion: 1\n\n\
+       Package: e\nArchitecture: amd64\nVersion: 2\nBreaks: f\n\n\
+       Package: b\nArchitecture: amd64\nVersion: 2\nDepends: c (>= 2)\n\n\
+       Package: c\nArchitecture: amd64\nVersion: 2\n\n\
+       Package: f\nArchitecture: amd64\nVersion: 1\n\n",
+    );
+    let upgrades = cache
+      .iter()
+      .filter(|c| c.version == "2")
+      .cloned()
+      .collect::<Vec<_>>();
+    let flags = DependencyFlags::default();
+
+    // upgrades needing new packages or removals are skipped, and the others are still done.
+    let constraints = Constraints {
+      forbid_new_install: true,
+      forbid_remove: true,
+    };
+    let solution = super::upgrade(&upgrades, &installed, &cache, flags, constraints).unwrap();
+    assert_eq!(names(&solution.upgrades), vec!["b=2", "c=2"]);
+    assert!(solution.installs.is_empty() && solution.removals.is_empty());
+
+    // without constraints, every upgrade is done.
+    let solution =
+      super::upgrade(&upgrades, &installed, &cache, flags, Constraints::default()).unwrap();
+    assert_eq!(names(&solution.upgrades), vec!["a=2", "e=2", "b=2", "c=2"]);
+    assert_eq!(names(&solution.installs), vec!["newdep=1"]);
+    assert_eq!(names(&solution.removals), vec!["f=1"]);
+  }
+
+  #[test]
+  fn test_resolve_deep_chain() {
+    // every Recommends is a branch, and the last one is not available.
+    let depth = 1500;
+    let cache = parse(
+      &(0..depth)
+        .map(|i| {
+          format!(
+            "Package: p{}\nArchitecture: amd64\nVersion: 1\nRecommends: p{}\n",
+            i,
+            i + 1
+          )
+        })
+        .collect::<Vec<_>>()
+        .join("\n"),
+    );
+    let solution = resolve(&cache[0..1], &[], &cache, DependencyFlags::default()).unwrap();
+    assert_eq!(solution.installs.len(), depth);
+    assert_eq!(names(&solution.installs[depth - 1..]), vec!["p1499=1"]);
+  }
+}
@@ -5,6 +5,8 @@ use glob::Pattern;
 
 // XXX for now, support only single glob term
 pub fn do_show(package: &str) {
+  // 'pkg:arch' shows packages of the architecture only.
+  let (package, arch) = source::split_qualified_name(package);
   let package_glob = match Pattern::new(package) {
     Ok(_package_glob) => _package_glob,
     Err(_) => {
@@ -12,7 +14,10 @@ pub fn do_show(package: &str) {
       return;
     }
   };
-  let found_items = dpkg::search_dpkg_with_name_glob(&package_glob, false);
+  let found_items = dpkg::search_dpkg_with_name_glob(&package_glob, false)
+    .into_iter()
+    .filter(|i| arch.is_none() || i.matches_arch(arch.unwrap()))
+    .collect::<Vec<_>>();
   let found_resolved_items = source::resolve_duplication(&found_items, None).unwrap();
 
   println!("");
@@ -23,7 +28,9 @@ pub fn list_packages(items: &Vec<SourcePackage>) {
   // XXX should show distro/arch, but dpkg/status doesn't have these info.
   // maybe, should show apt/lists/** for them.
   for item in items {
-    println!("Package: {}", item.package.green().bold());
+    println!("Package: {}", item.qualified_name().green().bold());
+    println!("Architecture: {}", item.arch_name());
+    println!("Multi-Arch: {}", item.multi_arch.to_string().to_lowercase());
     println!("Version: {}", item.version);
     println!("Priority: {}", item.priority.to_string().to_lowercase());
     println!("Section: {}", item.section.to_string().to_lowercase());
@@ -33,23 +40,20 @@ pub fn list_packages(items: &Vec<SourcePackage>) {
     println!("Maintainer: {}", item.maintainer);
     println!("Original-Maintainer: {}", item.original_maintainer);
     println!("Bugs: {}", item.bugs);
-    print!("Depends: ");
-    for dep in &item.depends {
-      match dep.1 {
-        Some(version) => {
-          print!("{} (>= {}), ", dep.0, version);
-        }
-        None => {
-          print!("{}, ", dep.0);
-        }
-      }
-    }
-    println!("");
-    print!("Suggests: ");
-    for pre in &item.suggests {
-      print!("{}, ", pre);
-    }
-    println!("");
+    println!(
+      "Provides: {}",
+      item
+        .provides
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+    );
+    println!("Pre-Depends: {}", source::format_depends(&item.pre_depends));
+    println!("Depends: {}", source::format_depends(&item.depends));
+    println!("Recommends: {}", source::format_depends(&item.recommends));
+    println!("Suggests: {}", source::format_depends(&item.suggests));
+    println!("Enhances: {}", source::format_depends(&item.enhances));
     println!("Homepage: {}", item.homepage);
     println!("Description: ");
     for d in item.description.split("\n").collect::<Vec<_>>() {
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum SourceType {
   DEB,
@@ -13,6 +15,20 @@ impl Default for SourceType {
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Protocol {
   HTTP,
+  HTTPS,
+  FILE,
+  COPY,
+}
+
+impl std::fmt::Display for Protocol {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::HTTP => write!(f, "http"),
+      Self::HTTPS => write!(f, "https"),
+      Self::FILE => write!(f, "file"),
+      Self::COPY => write!(f, "copy"),
+    }
+  }
 }
 
 impl Default for Protocol {
@@ -21,63 +37,247 @@ impl Default for Protocol {
   }
 }
 
-#[derive(Debug, PartialEq, Default, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Source {
   stype: SourceType,
   protocol: Protocol,
   uri: String,
   dists: String,
   component: String,
+  // options given by '[key=value ...]' or deb822 fields.
+  pub architectures: Vec<String>,
+  pub signed_by: Option<String>,
+  pub trusted: bool,
+  pub check_valid_until: bool,
+  pub languages: Vec<String>,
+  pub targets: Vec<String>,
+}
+
+impl Default for Source {
+  fn default() -> Self {
+    Self {
+      stype: SourceType::default(),
+      protocol: Protocol::default(),
+      uri: String::new(),
+      dists: String::new(),
+      component: String::new(),
+      architectures: vec![],
+      signed_by: None,
+      trusted: false,
+      check_valid_until: true,
+      languages: vec![],
+      targets: vec![],
+    }
+  }
 }
 
 impl Source {
-  pub fn to_filename(&self) -> String {
+  pub fn is_binary(&self) -> bool {
+    self.stype == SourceType::DEB
+  }
+
+  // whether index of @arch should be fetched from this source.
+  pub fn has_arch(&self, arch: &str) -> bool {
+    self.architectures.is_empty() || self.architectures.iter().any(|a| a == arch)
+  }
+
+  // whether index of @target('Packages', 'Translations', ...) should be fetched.
+  pub fn has_target(&self, target: &str) -> bool {
+    self.targets.is_empty() || self.targets.iter().any(|t| t == target)
+  }
+
+  // flat repository has no 'dists/' tree, and its suite is a path ending with '/'.
+  pub fn is_flat(&self) -> bool {
+    self.dists.ends_with('/')
+  }
+
+  // name of the index file of @arch in lists/.
+  // base URI can be restored from it by split_filename().
+  pub fn to_filename(&self, arch: &str) -> String {
+    if self.is_flat() {
+      // single index has packages of every architecture.
+      return format!(
+        "{}_flat_{}",
+        uri_to_filename(&self.base_uri()),
+        uri_to_filename(&self.dists)
+      );
+    }
     format!(
-      "{}_dists_{}-{}",
-      self.uri.replace("/", "_"),
-      self.dists,
-      self.component
+      "{}_dists_{}_{}_binary-{}",
+      uri_to_filename(&self.base_uri()),
+      escape_filename(&self.dists),
+      escape_filename(&self.component),
+      arch
     )
   }
 
   pub fn info(&self) -> String {
-    let proto = match self.protocol {
-      Protocol::HTTP => "http",
-    };
-    format!("{}://{} {} {}", proto, self.uri, self.dists, self.component)
+    format!(
+      "{}://{} {} {}",
+      self.protocol, self.uri, self.dists, self.component
+    )
+    .trim_end()
+    .to_string()
   }
 
-  pub fn to_index_uri(&self) -> String {
-    let mut iuri = String::new();
-    match self.protocol {
-      Protocol::HTTP => iuri.push_str("http"),
-    };
-    iuri.push_str("://");
-    iuri.push_str(&self.uri);
-    if self.uri.chars().nth(self.uri.len() - 1).unwrap() != '/' {
-      iuri.push_str("/");
-    };
-    iuri.push_str(&format!("dists/{}/", self.dists));
-    iuri.push_str(&format!("{}/", self.component));
-    iuri.push_str("binary-amd64/");
-    iuri.push_str("Packages.gz");
+  // URI of the repository root, always terminated by '/'.
+  pub fn base_uri(&self) -> String {
+    let mut buri = format!("{}://{}", self.protocol, self.uri);
+    if !buri.ends_with('/') {
+      buri.push('/');
+    }
+    buri
+  }
+
+  // URI of the directory which has Release files.
+  fn suite_uri(&self) -> String {
+    if self.is_flat() {
+      format!("{}{}", self.base_uri(), self.dists.trim_start_matches("./"))
+    } else {
+      format!("{}dists/{}/", self.base_uri(), self.dists)
+    }
+  }
+
+  pub fn to_release_uri(&self, name: &str) -> String {
+    format!("{}{}", self.suite_uri(), name)
+  }
+
+  pub fn to_index_uri(&self, arch: &str) -> String {
+    format!("{}{}", self.suite_uri(), self.to_index_path(arch))
+  }
+
+  // path of index file relative to the directory of Release file, as listed in it.
+  pub fn to_index_path(&self, arch: &str) -> String {
+    if self.is_flat() {
+      "Packages.gz".to_string()
+    } else {
+      format!("{}/binary-{}/Packages.gz", self.component, arch)
+    }
+  }
+
+  // @ret: architectures whose index should be fetched from this source.
+  pub fn index_archs(&self, archs: &[String]) -> Vec<String> {
+    if self.is_flat() {
+      return archs.iter().take(1).cloned().collect();
+    }
+    archs.iter().filter(|a| self.has_arch(a)).cloned().collect()
+  }
+}
+
+// same escaping as apt: '/' is replaced with '_', and '_' and '%' are escaped.
+pub fn uri_to_filename(uri: &str) -> String {
+  uri
+    .replace("%", "%25")
+    .replace("_", "%5f")
+    .replace("/", "_")
+}
+
+pub fn filename_to_uri(filename: &str) -> String {
+  filename
+    .replace("_", "/")
+    .replace("%5f", "_")
+    .replace("%25", "%")
+}
+
+// suite and component are escaped not to have '_', which separates them in file name.
+fn escape_filename(name: &str) -> String {
+  name
+    .replace("%", "%25")
+    .replace("_", "%5f")
+    .replace("/", "%2f")
+}
+
+fn unescape_filename(name: &str) -> String {
+  name
+    .replace("%2f", "/")
+    .replace("%5f", "_")
+    .replace("%25", "%")
+}
+
+// restore information of the source from the name of the index file in lists/.
+// @ret: (base URI, suite, component). suite of flat repository has no trailing '/',
+//       and its component is empty.
+pub fn split_filename(filename: &str) -> Option<(String, String, String)> {
+  if filename.ends_with('_') {
+    let ix = filename.rfind("_flat_")?;
+    let suite = filename_to_uri(&filename[ix + "_flat_".len()..]);
+    return Some((
+      filename_to_uri(&filename[..ix]),
+      suite.trim_end_matches('/').to_string(),
+      String::new(),
+    ));
+  }
+  let ix = filename.rfind("_dists_")?;
+  let mut part = &filename[ix + "_dists_".len()..];
+  if let Some(bx) = part.rfind("_binary-") {
+    part = &part[..bx];
+  }
+  let (suite, component) = part.split_once('_')?;
+  if component.contains('_') {
+    return None;
+  }
+  Some((
+    filename_to_uri(&filename[..ix]),
+    unescape_filename(suite),
+    unescape_filename(component),
+  ))
+}
+
+pub const DEFAULT_SOURCE_LIST: &str = "sources.list";
+pub const DEFAULT_SOURCE_PARTS: &str = "sources.list.d";
 
-    iuri
+// read @list_file and every '*.list' and '*.sources' file in @parts_dir.
+// missing file or directory is just ignored as apt does.
+pub fn load_sources(list_file: &str, parts_dir: &str) -> Result<Vec<Source>, String> {
+  let mut sources = vec![];
+  if Path::new(list_file).exists() {
+    sources.append(&mut parse_source_file(list_file)?);
+  }
+
+  let mut parts = vec![];
+  for pattern in &["*.list", "*.sources"] {
+    match glob::glob(&format!("{}/{}", parts_dir, pattern)) {
+      Ok(paths) => {
+        for entry in paths {
+          match entry {
+            Ok(path) => parts.push(path.to_str().unwrap().to_string()),
+            Err(msg) => return Err(format!("failed to open source list file: {}", msg)),
+          }
+        }
+      }
+      Err(_) => return Err(format!("invalid source list directory: {}", parts_dir)),
+    }
+  }
+  parts.sort();
+  for part in parts {
+    if part.ends_with(".sources") {
+      sources.append(&mut parse_deb822_file(&part)?);
+    } else {
+      sources.append(&mut parse_source_file(&part)?);
+    }
   }
+
+  if sources.is_empty() {
+    return Err(format!(
+      "No source found in {} and {}.",
+      list_file, parts_dir
+    ));
+  }
+  Ok(sources)
 }
 
 pub fn parse_source_file(filename: &str) -> Result<Vec<Source>, String> {
   let mut sources = vec![];
-  let source_lines = if let Ok(_s) = std::fs::read_to_string("sources.list") {
+  let source_lines = if let Ok(_s) = std::fs::read_to_string(filename) {
     _s
   } else {
     return Err(format!("Failed to open source list file: {}", filename));
   };
-  for line in source_lines.split("\n").collect::<Vec<_>>() {
+  for (ix, line) in source_lines.split("\n").enumerate() {
     if line.len() != 0 {
       match parse_source_line(line) {
         Ok(mut items) => sources.append(&mut items),
-        Err(msg) => return Err(msg),
+        Err(msg) => return Err(format!("{}:{}: {}", filename, ix + 1, msg)),
       }
     };
   }
@@ -86,27 +286,103 @@ pub fn parse_source_file(filename: &str) -> Result<Vec<Source>, String> {
 }
 
 pub fn parse_source_line(line: &str) -> Result<Vec<Source>, String> {
-  if line.len() == 0 {
+  if line.trim().is_empty() || line.trim_start().starts_with('#') {
     return Ok(vec![]);
   }
-  if line.chars().nth(0).unwrap() == '#' {
-    return Ok(vec![]);
+  let mut parts = line.split_whitespace().collect::<Vec<_>>();
+  let mut template = Source::default();
+
+  // options: deb [key=value key=value1,value2] uri suite components...
+  if parts.len() >= 2 && parts[1].starts_with('[') {
+    let end = match parts.iter().position(|p| p.ends_with(']')) {
+      Some(_end) => _end,
+      None => return Err("Malformed source line: unterminated option block.".to_string()),
+    };
+    let options = parts[1..end + 1].join(" ");
+    for option in options[1..options.len() - 1].split_whitespace() {
+      let kv = option.splitn(2, "=").collect::<Vec<_>>();
+      if kv.len() != 2 {
+        return Err(format!("Malformed source line: invalid option: {}", option));
+      }
+      let values = kv[1].split(",").map(|v| v.to_string()).collect::<Vec<_>>();
+      apply_option(&mut template, kv[0], values)?;
+    }
+    parts.drain(1..end + 1);
   }
-  let parts = line.split(" ").collect::<Vec<_>>();
-  if parts.len() < 4 {
+  // flat repository: deb uri path/
+  let flat = parts.len() >= 3 && parts[2].ends_with('/');
+  if parts.len() < 3 || (!flat && parts.len() < 4) {
     return Err(String::from("Malformed source line."));
   }
-  let stype = match parts[0] {
-    "deb" => SourceType::DEB,
-    "deb-src" => SourceType::DEBSRC,
-    _ => return Err(format!("Unknown source type: {}", parts[0])),
+  if flat && parts.len() > 3 {
+    return Err("Malformed source line: flat repository can't have components.".to_string());
+  }
+  template.stype = parse_source_type(parts[0])?;
+  let (protocol, uri) = parse_uri(parts[1])?;
+  template.protocol = protocol;
+  template.uri = uri;
+  template.dists = parts[2].to_string();
+  let components = if flat {
+    vec![&""]
+  } else {
+    parts[3..].iter().collect::<Vec<_>>()
+  };
+
+  Ok(
+    components
+      .iter()
+      .map(|component| Source {
+        component: component.to_string(),
+        ..template.clone()
+      })
+      .collect::<Vec<_>>(),
+  )
+}
+
+// @key is the name of one-line style option.
+fn apply_option(source: &mut Source, key: &str, values: Vec<String>) -> Result<(), String> {
+  let single = |values: &Vec<String>| match values.len() {
+    1 => Ok(values[0].clone()),
+    _ => Err(format!("option '{}' takes exactly one value.", key)),
   };
-  let _uri = parts[1].split("://").collect::<Vec<_>>();
+  let boolean = |values: &Vec<String>| match single(values)?.as_str() {
+    "yes" | "true" => Ok(true),
+    "no" | "false" => Ok(false),
+    value => Err(format!("invalid value of option '{}': {}", key, value)),
+  };
+
+  match key {
+    "arch" => source.architectures = values,
+    "signed-by" => source.signed_by = Some(single(&values)?),
+    "trusted" => source.trusted = boolean(&values)?,
+    "check-valid-until" => source.check_valid_until = boolean(&values)?,
+    // only 'Packages' indexes are fetched, so languages don't matter for now.
+    "lang" => source.languages = values,
+    "target" => source.targets = values,
+    _ => log::warn!("ignoring unsupported source option: {}", key),
+  }
+
+  Ok(())
+}
+
+fn parse_source_type(stype: &str) -> Result<SourceType, String> {
+  match stype {
+    "deb" => Ok(SourceType::DEB),
+    "deb-src" => Ok(SourceType::DEBSRC),
+    _ => Err(format!("Unknown source type: {}", stype)),
+  }
+}
+
+fn parse_uri(uri: &str) -> Result<(Protocol, String), String> {
+  let _uri = uri.split("://").collect::<Vec<_>>();
   if _uri.len() != 2 {
-    return Err(format!("Malformed source line: invalid uri: {}", parts[1]));
+    return Err(format!("Malformed source line: invalid uri: {}", uri));
   }
   let protocol = match _uri[0] {
     "http" => Protocol::HTTP,
+    "https" => Protocol::HTTPS,
+    "file" => Protocol::FILE,
+    "copy" => Protocol::COPY,
     _ => {
       return Err(format!(
         "Malformed source line: invalid protocol: {}",
@@ -114,22 +390,153 @@ pub fn parse_source_line(line: &str) -> Result<Vec<Source>, String> {
       ))
     }
   };
-  let uri = _uri[1];
-  let dists = parts[2];
-  let components = parts[3..].iter().collect::<Vec<_>>();
 
-  Ok(
-    components
-      .iter()
-      .map(|component| Source {
-        stype: stype,
-        protocol: protocol,
-        uri: uri.to_string(),
-        dists: dists.to_string(),
-        component: component.to_string(),
-      })
-      .collect::<Vec<_>>(),
-  )
+  Ok((protocol, _uri[1].to_string()))
+}
+
+// parse deb822-style source file (*.sources).
+pub fn parse_deb822_file(filename: &str) -> Result<Vec<Source>, String> {
+  let mut sources = vec![];
+  let raw = if let Ok(_s) = std::fs::read_to_string(filename) {
+    _s
+  } else {
+    return Err(format!("Failed to open source list file: {}", filename));
+  };
+
+  // (line number where the stanza starts, fields)
+  let mut stanza: Vec<(String, String)> = vec![];
+  let mut stanza_line = 0;
+  for (ix, line) in raw.split("\n").chain(std::iter::once("")).enumerate() {
+    if line.starts_with('#') {
+      continue;
+    }
+    if line.trim().is_empty() {
+      if !stanza.is_empty() {
+        match parse_deb822_stanza(&stanza) {
+          Ok(mut items) => sources.append(&mut items),
+          Err(msg) => return Err(format!("{}:{}: {}", filename, stanza_line, msg)),
+        }
+        stanza.clear();
+      }
+      continue;
+    }
+    if line.starts_with(' ') || line.starts_with('\t') {
+      // multi-line field continues
+      match stanza.last_mut() {
+        Some(field) => {
+          field.1.push('\n');
+          field.1.push_str(line.trim());
+        }
+        None => {
+          return Err(format!(
+            "{}:{}: continuation line without a field.",
+            filename,
+            ix + 1
+          ))
+        }
+      }
+      continue;
+    }
+    let kv = line.splitn(2, ":").collect::<Vec<_>>();
+    if kv.len() != 2 {
+      return Err(format!(
+        "{}:{}: malformed field: {}",
+        filename,
+        ix + 1,
+        line
+      ));
+    }
+    if stanza.is_empty() {
+      stanza_line = ix + 1;
+    }
+    stanza.push((kv[0].trim().to_lowercase(), kv[1].trim().to_string()));
+  }
+
+  Ok(sources)
+}
+
+fn parse_deb822_stanza(stanza: &[(String, String)]) -> Result<Vec<Source>, String> {
+  let field = |name: &str| {
+    stanza.iter().find(|f| f.0 == name).map(|f| {
+      f.1
+        .split_whitespace()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>()
+    })
+  };
+  let required = |name: &str, display: &str| match field(name) {
+    Some(values) if !values.is_empty() => Ok(values),
+    _ => Err(format!("missing '{}' field.", display)),
+  };
+
+  if let Some(enabled) = field("enabled") {
+    if enabled.iter().any(|e| e == "no") {
+      return Ok(vec![]);
+    }
+  }
+  let types = required("types", "Types")?;
+  let uris = required("uris", "URIs")?;
+  let suites = required("suites", "Suites")?;
+  // flat repositories don't have components.
+  let components = if suites.iter().all(|s| s.ends_with('/')) {
+    field("components").unwrap_or_default()
+  } else {
+    required("components", "Components")?
+  };
+  if let Some(f) = stanza.iter().find(|f| f.0 == "signed-by") {
+    if f.1.contains("BEGIN PGP PUBLIC KEY BLOCK") {
+      return Err("embedded keys in 'Signed-By' are not supported.".to_string());
+    }
+  }
+
+  // deb822 field name and corresponding one-line style option.
+  let mut template = Source::default();
+  let options = vec![
+    ("architectures", "arch"),
+    ("signed-by", "signed-by"),
+    ("trusted", "trusted"),
+    ("check-valid-until", "check-valid-until"),
+    ("languages", "lang"),
+    ("targets", "target"),
+  ];
+  for (name, key) in options {
+    if let Some(values) = field(name) {
+      apply_option(&mut template, key, values)?;
+    }
+  }
+
+  let mut sources = vec![];
+  for stype in &types {
+    let stype = parse_source_type(stype)?;
+    for uri in &uris {
+      let (protocol, uri) = parse_uri(uri)?;
+      for suite in &suites {
+        let components = if suite.ends_with('/') {
+          if !components.is_empty() {
+            return Err(format!(
+              "flat repository '{}' can't have components.",
+              suite
+            ));
+          }
+          vec![String::new()]
+        } else {
+          components.clone()
+        };
+        for component in &components {
+          sources.push(Source {
+            stype,
+            protocol,
+            uri: uri.clone(),
+            dists: suite.clone(),
+            component: component.clone(),
+            ..template.clone()
+          });
+        }
+      }
+    }
+  }
+
+  Ok(sources)
 }
 
 #[cfg(test)]
@@ -143,6 +550,7 @@ pub mod tests {
       uri: "jp.archive.ubuntu.com/ubuntu/".to_string(),
       dists: "focal".to_string(),
       component: "main".to_string(),
+      ..Default::default()
     };
     let s2 = super::Source {
       stype: super::SourceType::DEB,
@@ -150,20 +558,221 @@ pub mod tests {
       uri: "jp.archive.ubuntu.com/ubuntu/".to_string(),
       dists: "focal".to_string(),
       component: "restricted".to_string(),
+      ..Default::default()
     };
     let sources = super::parse_source_line(line).unwrap();
     assert_eq!(sources[0], s1);
     assert_eq!(sources[1], s2);
   }
 
+  #[test]
+  pub fn test_parse_source_line_signed_by() {
+    let line = "deb [signed-by=/usr/share/keyrings/ubuntu.gpg] http://jp.archive.ubuntu.com/ubuntu/ focal main";
+    let sources = super::parse_source_line(line).unwrap();
+    assert_eq!(sources.len(), 1);
+    assert_eq!(
+      sources[0].signed_by,
+      Some("/usr/share/keyrings/ubuntu.gpg".to_string())
+    );
+    assert_eq!(
+      sources[0].to_release_uri("InRelease"),
+      "http://jp.archive.ubuntu.com/ubuntu/dists/focal/InRelease"
+    );
+  }
+
+  #[test]
+  pub fn test_parse_source_line_options() {
+    let line = "deb [ arch=amd64,i386 signed-by=/usr/share/keyrings/x.gpg trusted=yes check-valid-until=no ]  http://jp.archive.ubuntu.com/ubuntu/ focal main";
+    let source = &super::parse_source_line(line).unwrap()[0];
+    assert_eq!(
+      source.architectures,
+      vec!["amd64".to_string(), "i386".to_string()]
+    );
+    assert_eq!(
+      source.signed_by,
+      Some("/usr/share/keyrings/x.gpg".to_string())
+    );
+    assert!(source.trusted);
+    assert!(!source.check_valid_until);
+    assert_eq!(
+      source.info(),
+      "http://jp.archive.ubuntu.com/ubuntu/ focal main"
+    );
+    assert!(source.has_arch("i386"));
+    assert!(!source.has_arch("arm64"));
+
+    let default =
+      &super::parse_source_line("deb http://jp.archive.ubuntu.com/ubuntu/ focal main").unwrap()[0];
+    assert!(!default.trusted);
+    assert!(default.check_valid_until);
+    assert!(default.has_arch("arm64"));
+
+    assert!(super::parse_source_line("deb [arch=amd64 http://a/ focal main").is_err());
+    assert!(super::parse_source_line("deb [trusted=maybe] http://a/ focal main").is_err());
+
+    let line = "deb [lang=en,ja target=Contents-deb] http://a/ focal main";
+    let source = &super::parse_source_line(line).unwrap()[0];
+    assert_eq!(source.languages, vec!["en".to_string(), "ja".to_string()]);
+    assert!(!source.has_target("Packages"));
+    assert!(default.has_target("Packages"));
+  }
+
+  #[test]
+  pub fn test_to_filename() {
+    let line = "deb https://mirror.example.com/my_repo/ubuntu focal-updates main";
+    let source = &super::parse_source_line(line).unwrap()[0];
+    let filename = source.to_filename("amd64");
+    assert_eq!(
+      filename,
+      "https:__mirror.example.com_my%5frepo_ubuntu__dists_focal-updates_main_binary-amd64"
+    );
+    assert_eq!(
+      super::split_filename(&filename).unwrap(),
+      (
+        "https://mirror.example.com/my_repo/ubuntu/".to_string(),
+        "focal-updates".to_string(),
+        "main".to_string()
+      )
+    );
+    // '-', '_' and '/' in suite or component don't confuse the split.
+    let line = "deb http://deb.example.com/debian focal-updates non-free restricted_foo/bar";
+    let sources = super::parse_source_line(line).unwrap();
+    let filename = sources[0].to_filename("i386");
+    assert_eq!(
+      filename,
+      "http:__deb.example.com_debian__dists_focal-updates_non-free_binary-i386"
+    );
+    assert_eq!(
+      super::split_filename(&filename).unwrap(),
+      (
+        "http://deb.example.com/debian/".to_string(),
+        "focal-updates".to_string(),
+        "non-free".to_string()
+      )
+    );
+    assert_eq!(
+      super::split_filename(&sources[1].to_filename("i386"))
+        .unwrap()
+        .2,
+      "restricted_foo/bar"
+    );
+    // lists named in the old format are not recognized.
+    assert!(super::split_filename("http:__a_debian__dists_focal-main_binary-i386").is_none());
+
+    let line = "deb file:///srv/repo focal main";
+    let source = &super::parse_source_line(line).unwrap()[0];
+    assert_eq!(
+      source.to_index_uri("amd64"),
+      "file:///srv/repo/dists/focal/main/binary-amd64/Packages.gz"
+    );
+    assert!(super::parse_source_line("deb ftp://example.com/ focal main").is_err());
+  }
+
   #[test]
   pub fn test_to_index_uri() {
     let line = "deb http://jp.archive.ubuntu.com/ubuntu/ focal main";
     let source = &super::parse_source_line(line).unwrap()[0];
-    let uri = source.to_index_uri();
+    let uri = source.to_index_uri("amd64");
     assert_eq!(
       uri,
       "http://jp.archive.ubuntu.com/ubuntu/dists/focal/main/binary-amd64/Packages.gz"
     );
+    assert_eq!(
+      source.to_index_uri("i386"),
+      "http://jp.archive.ubuntu.com/ubuntu/dists/focal/main/binary-i386/Packages.gz"
+    );
+
+    let archs = vec!["amd64".to_string(), "i386".to_string(), "arm64".to_string()];
+    assert_eq!(source.index_archs(&archs), archs);
+    let line = "deb [arch=amd64,i386] http://jp.archive.ubuntu.com/ubuntu/ focal main";
+    let source = &super::parse_source_line(line).unwrap()[0];
+    assert_eq!(source.index_archs(&archs), archs[..2].to_vec());
+    let source = &super::parse_source_line("deb http://example.com/repo ./").unwrap()[0];
+    assert_eq!(source.index_archs(&archs), archs[..1].to_vec());
+  }
+
+  #[test]
+  pub fn test_flat_repository() {
+    let source =
+      &super::parse_source_line("deb [trusted=yes] http://example.com/repo ./").unwrap()[0];
+    assert!(source.is_flat());
+    assert_eq!(
+      source.to_release_uri("InRelease"),
+      "http://example.com/repo/InRelease"
+    );
+    assert_eq!(
+      source.to_index_uri("amd64"),
+      "http://example.com/repo/Packages.gz"
+    );
+    assert_eq!(source.to_index_path("i386"), "Packages.gz");
+    assert_eq!(source.info(), "http://example.com/repo ./");
+    assert_eq!(
+      super::split_filename(&source.to_filename("amd64")).unwrap(),
+      (
+        "http://example.com/repo/".to_string(),
+        ".".to_string(),
+        "".to_string()
+      )
+    );
+
+    let source = &super::parse_source_line("deb http://example.com/flat/ amd64/").unwrap()[0];
+    assert_eq!(
+      source.to_index_uri("amd64"),
+      "http://example.com/flat/amd64/Packages.gz"
+    );
+    assert_eq!(
+      super::split_filename(&source.to_filename("amd64")).unwrap(),
+      (
+        "http://example.com/flat/".to_string(),
+        "amd64".to_string(),
+        "".to_string()
+      )
+    );
+
+    assert!(super::parse_source_line("deb http://example.com/repo ./ main").is_err());
+    assert!(super::parse_source_line("deb http://example.com/repo focal").is_err());
+  }
+
+  #[test]
+  pub fn test_parse_deb822_file() {
+    let sources = super::parse_deb822_file("test/sources.list.d/ubuntu.sources").unwrap();
+    // disabled stanza is skipped, and multi-line field is joined.
+    assert_eq!(sources.len(), 7);
+    let line = "deb [signed-by=/usr/share/keyrings/ubuntu-archive-keyring.gpg] http://jp.archive.ubuntu.com/ubuntu/ focal main";
+    let mut expected = super::parse_source_line(line).unwrap().remove(0);
+    expected.architectures = vec!["amd64".to_string()];
+    assert_eq!(sources[0], expected);
+    assert_eq!(sources[1].component, "restricted");
+    assert_eq!(sources[2].dists, "focal-updates");
+    assert!(!sources[4].is_binary());
+    assert!(sources[6].trusted);
+    assert!(!sources[6].check_valid_until);
+    assert_eq!(
+      sources[6].architectures,
+      vec!["amd64".to_string(), "i386".to_string()]
+    );
+    assert_eq!(sources[6].languages, vec!["en".to_string()]);
+    assert!(sources[6].has_target("Packages"));
+  }
+
+  #[test]
+  pub fn test_parse_deb822_file_error() {
+    let msg = super::parse_deb822_file("test/sources.list.d/broken.sources.invalid").unwrap_err();
+    assert_eq!(
+      msg,
+      "test/sources.list.d/broken.sources.invalid:6: missing 'Suites' field."
+    );
+  }
+
+  #[test]
+  pub fn test_load_sources() {
+    let sources = super::load_sources("test/no-such-sources.list", "test/sources.list.d").unwrap();
+    // files in the directory are read in alphabetical order.
+    assert_eq!(sources.len(), 8);
+    assert_eq!(
+      sources[0].info(),
+      "http://archive.canonical.com/ubuntu focal partner"
+    );
+    assert!(super::load_sources("test/no-such-sources.list", "test/no-such-dir").is_err());
   }
 }
@@ -1,4 +1,5 @@
 use crate::cache;
+use crate::config::CONFIG;
 use crate::dpkg;
 use crate::version::*;
 use colored::*;
@@ -22,11 +23,19 @@ pub static DPKG_CACHE: Lazy<Vec<SourcePackage>> = Lazy::new(|| {
   println!(" DONE");
   return items;
 });
-pub static EXTENDED_CACHE: Lazy<Vec<(String, bool)>> = Lazy::new(|| {
+// virtual package name -> indices of the packages which provide it.
+pub type ProviderIndex = HashMap<String, Vec<usize>>;
+pub static CACHE_PROVIDERS: Lazy<ProviderIndex> = Lazy::new(|| build_provider_index(&CACHE));
+pub static DPKG_PROVIDERS: Lazy<ProviderIndex> = Lazy::new(|| build_provider_index(&DPKG_CACHE));
+// auto-installed flags recorded by rapt.
+pub const EXTENDED_STATES: &str = "apt/extended_states";
+// (package name, architecture, auto-installed)
+pub type ExtendedState = (String, String, bool);
+pub static EXTENDED_CACHE: Lazy<Vec<ExtendedState>> = Lazy::new(|| {
   print!("Reading extended states: ");
   std::io::stdout().flush().unwrap();
   // for now, read rapt's extended_states and actual apt's one.
-  let mut items = read_extended_information("apt/extended_states");
+  let mut items = read_extended_information(EXTENDED_STATES);
   items.append(&mut read_extended_information(
     "/var/lib/apt/extended_states",
   ));
@@ -84,9 +93,9 @@ impl Default for StatusStatus {
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct Status {
   // status field exists only in dpkg/status(in .deb file)
-  want: StatusWant,
-  flag: StatusFlag,
-  status: StatusStatus,
+  pub want: StatusWant,
+  pub flag: StatusFlag,
+  pub status: StatusStatus,
 }
 
 #[derive(Debug, PartialEq, Default, Clone)]
@@ -95,6 +104,7 @@ pub struct SourcePackage {
   pub status: Status,
   pub binary: Vec<String>,
   pub arch: Vec<Arch>,
+  pub multi_arch: MultiArch,
   pub version: String,
   pub priority: Priority,
   pub section: Section,
@@ -102,14 +112,22 @@ pub struct SourcePackage {
   pub original_maintainer: String,
   pub uploaders: Vec<String>,
   pub standard_version: String,
-  pub depends: HashMap<String, Option<String>>,
-  pub pre_depends: HashMap<String, Option<String>>,
+  pub depends: Vec<OrGroup>,
+  pub pre_depends: Vec<OrGroup>,
+  pub conflicts: Vec<OrGroup>,
+  pub breaks: Vec<OrGroup>,
+  pub replaces: Vec<OrGroup>,
   pub testsuite: String,
   pub homepage: String,
   pub directory: String,
   pub chksum_md5: String,
+  pub chksum_sha1: String,
+  pub chksum_sha256: String,
+  pub size: u64,
   pub essential: bool,
-  pub suggests: Vec<String>,
+  pub recommends: Vec<OrGroup>,
+  pub suggests: Vec<OrGroup>,
+  pub enhances: Vec<OrGroup>,
   pub filename: String,
   pub description: String,
   pub conffiles: Vec<String>,
@@ -119,20 +137,16 @@ pub struct SourcePackage {
   pub download_size: u64,
   pub component: String,
   pub dist: String,
+  // index file in lists/ which this package is read from.
+  pub list: String,
   pub apt_manual_installed: bool,
-  pub provides: Vec<String>,
+  pub provides: Vec<Relation>,
 }
 
 impl SourcePackage {
-  pub fn to_pool_uri(&self) -> Result<String, ()> {
+  pub fn to_pool_uri(&self) -> Result<String, String> {
     let mut puri = String::new();
-    puri.push_str("http");
-    puri.push_str("://");
-    puri.push_str("");
-    match cache::get_pool_domain(self) {
-      Ok(domain) => puri.push_str(&domain),
-      Err(()) => return Err(()),
-    };
+    puri.push_str(&cache::get_pool_base(self)?);
     puri.push_str(&self.filename);
 
     Ok(puri)
@@ -212,6 +226,7 @@ impl SourcePackage {
           // add info of filename here
           item.component = component.clone();
           item.dist = dist.clone();
+          item.list = filename.to_string();
         }
         "Status" => {
           item.status = parse_status(parts.nth(0).unwrap())?;
@@ -220,12 +235,7 @@ impl SourcePackage {
           let arch = parts
             .nth(0)
             .ok_or(format!("invalid 'Architecture' format: {}", line))?;
-          item.arch.push(match *arch {
-            "amd64" => Arch::AMD64,
-            "all" => Arch::ALL,
-            "any" => Arch::ANY,
-            _ => Arch::UNKNOWN,
-          });
+          item.arch.push(Arch::from_name(arch));
         }
         "Version" => {
           item.version = parts
@@ -262,6 +272,17 @@ impl SourcePackage {
             .parse()
             .unwrap_or(0);
         }
+        "Multi-Arch" => {
+          item.multi_arch = match *parts
+            .nth(0)
+            .ok_or(format!("invalid 'Multi-Arch' format: {}", line))?
+          {
+            "same" => MultiArch::SAME,
+            "foreign" => MultiArch::FOREIGN,
+            "allowed" => MultiArch::ALLOWED,
+            _ => MultiArch::NO,
+          };
+        }
         "Essential" => {
           item.essential = match *parts
             .nth(0)
@@ -294,44 +315,43 @@ impl SourcePackage {
             .join(" ");
         }
         "Pre-Depends" => {
-          let _depends = parts.nth(0).unwrap();
-          let depends = _depends.split(",").collect::<Vec<_>>();
-          for dep in depends {
-            match parse_depends(dep) {
-              Ok((pkg, version)) => item.pre_depends.insert(pkg, version),
-              Err(msg) => return Err(msg),
-            };
-          }
+          item.pre_depends = parse_depends(parts.nth(0).unwrap())?;
         }
         "Depends" => {
-          let _depends = parts.nth(0).unwrap();
-          let depends = _depends.split(",").collect::<Vec<_>>();
-          for dep in depends {
-            match parse_depends(dep) {
-              Ok((pkg, version)) => item.depends.insert(pkg, version),
-              Err(msg) => return Err(msg),
-            };
-          }
+          item.depends = parse_depends(parts.nth(0).unwrap())?;
+        }
+        "Conflicts" => {
+          item.conflicts = parse_depends(parts.nth(0).unwrap())?;
+        }
+        "Breaks" => {
+          item.breaks = parse_depends(parts.nth(0).unwrap())?;
+        }
+        "Replaces" => {
+          item.replaces = parse_depends(parts.nth(0).unwrap())?;
         }
         "Provides" => {
-          let _depends = parts.nth(0).unwrap();
-          let depends = _depends.split(",").collect::<Vec<_>>();
-          for dep in depends {
-            match parse_depends(dep) {
-              Ok((pkg, _version)) => item.provides.push(pkg),
-              Err(msg) => return Err(msg),
-            };
+          for group in parse_depends(parts.nth(0).unwrap())? {
+            for relation in group {
+              match &relation.version {
+                None | Some((RelOp::EQ, _)) => item.provides.push(relation),
+                // only '=' is allowed. the rest of the index is still usable.
+                Some(_) => log::warn!(
+                  "ignoring invalid 'Provides' of {}: {}",
+                  item.package,
+                  relation
+                ),
+              }
+            }
           }
         }
+        "Recommends" => {
+          item.recommends = parse_depends(parts.nth(0).unwrap())?;
+        }
         "Suggests" => {
-          let _sug = parts.map(|s| String::from(*s)).collect::<Vec<_>>().join("");
-          let sug = _sug.split(",").map(|s| s.trim()).collect::<Vec<_>>();
-          for s in sug {
-            item.suggests.push(s.to_string());
-          }
+          item.suggests = parse_depends(parts.nth(0).unwrap())?;
         }
-        "Breaks" => {
-          //log::debug!("ignoring Breaks.");
+        "Enhances" => {
+          item.enhances = parse_depends(parts.nth(0).unwrap())?;
         }
         "Filename" => {
           item.filename = parts
@@ -345,6 +365,26 @@ impl SourcePackage {
             .ok_or(format!("invalid 'MD5sum' format: {}", line))?
             .to_string();
         }
+        "SHA1" => {
+          item.chksum_sha1 = parts
+            .nth(0)
+            .ok_or(format!("invalid 'SHA1' format: {}", line))?
+            .to_string();
+        }
+        "SHA256" => {
+          item.chksum_sha256 = parts
+            .nth(0)
+            .ok_or(format!("invalid 'SHA256' format: {}", line))?
+            .to_string();
+        }
+        "Size" => {
+          item.size = parts
+            .nth(0)
+            .ok_or(format!("invalid 'Size' format: {}", line))?
+            .to_string()
+            .parse()
+            .unwrap_or(0);
+        }
         "Homepage" => {
           item.homepage = parts
             .nth(0)
@@ -432,30 +472,179 @@ impl SourcePackage {
     Ok(items)
   }
 
+  // @ret: name of the architecture, or empty if unknown.
+  pub fn arch_name(&self) -> String {
+    match self.arch.first() {
+      Some(arch) => arch.to_string(),
+      None => "".to_string(),
+    }
+  }
+
+  // whether the package is installable on @arch.
+  pub fn matches_arch(&self, arch: &str) -> bool {
+    self.arch.is_empty()
+      || self
+        .arch
+        .iter()
+        .any(|a| *a == Arch::ALL || a.to_string() == arch)
+  }
+
+  // 'pkg:arch' regardless of the architecture, used to identify a package in the caches.
+  pub fn arch_qualified_name(&self) -> String {
+    format!("{}:{}", self.package, self.arch_name())
+  }
+
+  // whether this package satisfies a dependency with arch @qualifier('any', 'native' or
+  // an architecture) from a package of @depender_arch, following Multi-Arch rules.
+  // 'all' packages are regarded as ones of native architecture.
+  pub fn satisfies_arch(&self, qualifier: Option<&str>, depender_arch: &str) -> bool {
+    let native = CONFIG.architecture.as_str();
+    let as_native = |arch: &str| {
+      if arch.is_empty() || arch == "all" {
+        native.to_string()
+      } else {
+        arch.to_string()
+      }
+    };
+    let own = as_native(&self.arch_name());
+    match qualifier {
+      None => self.multi_arch == MultiArch::FOREIGN || own == as_native(depender_arch),
+      // only packages which allow it can satisfy ':any'.
+      Some("any") => self.multi_arch == MultiArch::ALLOWED,
+      Some("native") => self.multi_arch == MultiArch::FOREIGN || own == native,
+      Some(arch) => own == as_native(arch),
+    }
+  }
+
+  // whether the package is put on hold by dpkg selections, not to be upgraded or removed.
+  pub fn is_held(&self) -> bool {
+    self.status.want == StatusWant::HOLD
+  }
+
+  // 'pkg:arch' for packages of foreign architecture, and just 'pkg' for the others.
+  pub fn qualified_name(&self) -> String {
+    let arch = self.arch_name();
+    if arch.is_empty() || arch == "all" || arch == CONFIG.architecture {
+      self.package.clone()
+    } else {
+      format!("{}:{}", self.package, arch)
+    }
+  }
+
   pub fn verify(&self) -> Result<(), String> {
     Ok(())
   }
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[allow(non_camel_case_types)]
 pub enum Arch {
   ALL,
   ANY,
+  // official release architectures
   AMD64,
+  ARM64,
+  ARMEL,
+  ARMHF,
+  I386,
+  MIPS64EL,
+  MIPSEL,
+  PPC64EL,
+  S390X,
+  // ports and others in Debian architecture table
+  ALPHA,
+  ARC,
+  HPPA,
+  IA64,
+  LOONG64,
+  M68K,
+  MIPS,
+  MIPS64,
+  POWERPC,
+  PPC64,
+  RISCV64,
+  S390,
+  SH4,
+  SPARC,
+  SPARC64,
+  X32,
+  HURD_I386,
+  HURD_AMD64,
+  KFREEBSD_I386,
+  KFREEBSD_AMD64,
   UNKNOWN,
 }
 
+#[derive(Debug, PartialEq, Copy, Clone, Display, Default)]
+pub enum MultiArch {
+  #[default]
+  NO,
+  SAME,
+  FOREIGN,
+  ALLOWED,
+}
+
+// Arch and its name in the index.
+const ARCH_TABLE: &[(Arch, &str)] = &[
+  (Arch::ALL, "all"),
+  (Arch::ANY, "any"),
+  (Arch::AMD64, "amd64"),
+  (Arch::ARM64, "arm64"),
+  (Arch::ARMEL, "armel"),
+  (Arch::ARMHF, "armhf"),
+  (Arch::I386, "i386"),
+  (Arch::MIPS64EL, "mips64el"),
+  (Arch::MIPSEL, "mipsel"),
+  (Arch::PPC64EL, "ppc64el"),
+  (Arch::S390X, "s390x"),
+  (Arch::ALPHA, "alpha"),
+  (Arch::ARC, "arc"),
+  (Arch::HPPA, "hppa"),
+  (Arch::IA64, "ia64"),
+  (Arch::LOONG64, "loong64"),
+  (Arch::M68K, "m68k"),
+  (Arch::MIPS, "mips"),
+  (Arch::MIPS64, "mips64"),
+  (Arch::POWERPC, "powerpc"),
+  (Arch::PPC64, "ppc64"),
+  (Arch::RISCV64, "riscv64"),
+  (Arch::S390, "s390"),
+  (Arch::SH4, "sh4"),
+  (Arch::SPARC, "sparc"),
+  (Arch::SPARC64, "sparc64"),
+  (Arch::X32, "x32"),
+  (Arch::HURD_I386, "hurd-i386"),
+  (Arch::HURD_AMD64, "hurd-amd64"),
+  (Arch::KFREEBSD_I386, "kfreebsd-i386"),
+  (Arch::KFREEBSD_AMD64, "kfreebsd-amd64"),
+];
+
+impl Arch {
+  pub fn from_name(name: &str) -> Self {
+    match ARCH_TABLE.iter().find(|a| a.1 == name) {
+      Some(a) => a.0,
+      None => Self::UNKNOWN,
+    }
+  }
+}
+
 impl std::fmt::Display for Arch {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    match self {
-      Self::ALL => write!(f, "all"),
-      Self::ANY => write!(f, "any"),
-      Self::AMD64 => write!(f, "amd64"),
-      Self::UNKNOWN => write!(f, "unknown"),
+    match ARCH_TABLE.iter().find(|a| a.0 == *self) {
+      Some(a) => write!(f, "{}", a.1),
+      None => write!(f, "unknown"),
     }
   }
 }
 
+// split 'pkg:arch' into name and architecture qualifier.
+pub fn split_qualified_name(name: &str) -> (&str, Option<&str>) {
+  match name.find(':') {
+    Some(ix) => (&name[..ix], Some(&name[ix + 1..])),
+    None => (name, None),
+  }
+}
+
 #[derive(Debug, PartialEq, Copy, Clone, Display)]
 pub enum Priority {
   REQUIRED,
@@ -570,32 +759,300 @@ impl Default for Section {
   }
 }
 
-// XXX should hold version info with '>', '=', '>=', etc...
-pub fn parse_depends(_dep: &str) -> Result<(String, Option<String>), String> {
-  let tmp = _dep.trim().to_string();
-  if tmp.contains("|") {
-    let dep = tmp.split("|").collect::<Vec<_>>()[0];
-    parse_depends(&dep)
-  } else {
-    let dep = tmp.split(" ").collect::<Vec<_>>();
-    // 0: package, 1: ( and {>=<}, 2: version and )
-    match dep.len() {
-      1 => Ok((dep[0].trim().to_string(), None)),
-      2 => Err(format!(
-        "Invalid Depends/Pre-Depends/Provides field: {}",
-        _dep
-      )),
-      3 => {
-        let pkg = dep[0].trim().to_string();
-        let version = &dep[2][..dep[2].len() - 1];
-        Ok((pkg, Some(version.trim().to_string())))
+// relation operator in the version restriction of a relationship.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RelOp {
+  LT, // <<
+  LE, // <=
+  EQ, // =
+  GE, // >=
+  GT, // >>
+}
+
+impl RelOp {
+  pub fn parse(op: &str) -> Result<Self, String> {
+    match op {
+      "<<" => Ok(Self::LT),
+      // '<' and '>' are obsolete forms of '<=' and '>='.
+      "<=" | "<" => Ok(Self::LE),
+      "=" => Ok(Self::EQ),
+      ">=" | ">" => Ok(Self::GE),
+      ">>" => Ok(Self::GT),
+      _ => Err(format!("unknown relation operator: {}", op)),
+    }
+  }
+
+  // @cmp: result of comp_version(actual, required)
+  pub fn matches(&self, cmp: i32) -> bool {
+    match self {
+      Self::LT => cmp < 0,
+      Self::LE => cmp <= 0,
+      Self::EQ => cmp == 0,
+      Self::GE => cmp >= 0,
+      Self::GT => cmp > 0,
+    }
+  }
+}
+
+impl std::fmt::Display for RelOp {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::LT => write!(f, "<<"),
+      Self::LE => write!(f, "<="),
+      Self::EQ => write!(f, "="),
+      Self::GE => write!(f, ">="),
+      Self::GT => write!(f, ">>"),
+    }
+  }
+}
+
+// a term of architecture restriction or build profile, optionally negated by '!'.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Restriction {
+  pub negated: bool,
+  pub name: String,
+}
+
+impl std::fmt::Display for Restriction {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}{}", if self.negated { "!" } else { "" }, self.name)
+  }
+}
+
+// single relationship such as 'libc6:any (>= 2.15) [amd64 !i386] <!nocheck>'.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Relation {
+  pub name: String,
+  // 'any', 'native' or an architecture following ':'
+  pub arch_qualifier: Option<String>,
+  pub version: Option<(RelOp, String)>,
+  pub arch_restrictions: Vec<Restriction>,
+  // each '<...>' is a list of terms to be all satisfied, and any of them is enough.
+  pub profiles: Vec<Vec<Restriction>>,
+}
+
+// alternatives joined by '|', any of which satisfies the dependency.
+pub type OrGroup = Vec<Relation>;
+
+impl Relation {
+  // 'pkg' or 'pkg:qualifier'
+  pub fn qualified_name(&self) -> String {
+    match &self.arch_qualifier {
+      Some(qualifier) => format!("{}:{}", self.name, qualifier),
+      None => self.name.clone(),
+    }
+  }
+
+  pub fn satisfied_by_version(&self, version: &str) -> bool {
+    match &self.version {
+      Some((op, required)) => op.matches(comp_version(version, required)),
+      None => true,
+    }
+  }
+
+  // whether @package fulfils the relationship by itself or by its 'Provides'.
+  // unversioned 'Provides' never satisfies versioned relationship.
+  pub fn satisfied_by(&self, package: &SourcePackage) -> bool {
+    if package.package == self.name {
+      return self.satisfied_by_version(&package.version);
+    }
+    package
+      .provides
+      .iter()
+      .filter(|p| p.name == self.name)
+      .any(|p| match (&self.version, &p.version) {
+        (None, _) => true,
+        (Some(_), Some((RelOp::EQ, provided))) => self.satisfied_by_version(provided),
+        _ => false,
+      })
+  }
+
+  // whether the relationship is effective on @arch. 'all' means native architecture.
+  pub fn applies_to_arch(&self, arch: &str) -> bool {
+    if self.arch_restrictions.is_empty() {
+      return true;
+    }
+    let arch = if arch.is_empty() || arch == "all" {
+      CONFIG.architecture.as_str()
+    } else {
+      arch
+    };
+    // restrictions are either all negated or all not negated.
+    if self.arch_restrictions.iter().all(|r| r.negated) {
+      self.arch_restrictions.iter().all(|r| r.name != arch)
+    } else {
+      self
+        .arch_restrictions
+        .iter()
+        .any(|r| !r.negated && r.name == arch)
+    }
+  }
+
+  // whether the relationship is effective with @active build profiles.
+  pub fn applies_to_profiles(&self, active: &[String]) -> bool {
+    if self.profiles.is_empty() {
+      return true;
+    }
+    self
+      .profiles
+      .iter()
+      .any(|terms| terms.iter().all(|t| active.contains(&t.name) != t.negated))
+  }
+}
+
+impl std::fmt::Display for Relation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.qualified_name())?;
+    if let Some((op, version)) = &self.version {
+      write!(f, " ({} {})", op, version)?;
+    }
+    if !self.arch_restrictions.is_empty() {
+      let archs = self
+        .arch_restrictions
+        .iter()
+        .map(|r| r.to_string())
+        .collect::<Vec<_>>();
+      write!(f, " [{}]", archs.join(" "))?;
+    }
+    for terms in &self.profiles {
+      let terms = terms.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+      write!(f, " <{}>", terms.join(" "))?;
+    }
+    Ok(())
+  }
+}
+
+// @ret: index from names provided by @items to their positions in @items.
+pub fn build_provider_index(items: &[SourcePackage]) -> ProviderIndex {
+  let mut index = ProviderIndex::new();
+  for (ix, item) in items.iter().enumerate() {
+    for provided in &item.provides {
+      let providers = index.entry(provided.name.clone()).or_default();
+      // the same name may be provided several times with different versions.
+      if providers.last() != Some(&ix) {
+        providers.push(ix);
       }
-      _ => Err(format!(
-        "Invalid Depends/Pre-Depends/Provides field: {}",
-        _dep
-      )),
     }
   }
+  index
+}
+
+// @ret: packages in @items which provide @name, looked up by @index built from @items.
+pub fn find_providers<'a>(
+  items: &'a [SourcePackage],
+  index: &ProviderIndex,
+  name: &str,
+) -> Vec<&'a SourcePackage> {
+  match index.get(name) {
+    Some(ixs) => ixs.iter().map(|ix| &items[*ix]).collect(),
+    None => vec![],
+  }
+}
+
+// format relationship field back from @groups.
+pub fn format_depends(groups: &[OrGroup]) -> String {
+  groups
+    .iter()
+    .map(|g| {
+      g.iter()
+        .map(|r| r.to_string())
+        .collect::<Vec<_>>()
+        .join(" | ")
+    })
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+// parse relationship field such as Depends, Pre-Depends and Provides.
+pub fn parse_depends(_dep: &str) -> Result<Vec<OrGroup>, String> {
+  let mut groups = vec![];
+  for group in _dep.split(',') {
+    if group.trim().is_empty() {
+      continue; // trailing comma
+    }
+    let mut relations = vec![];
+    for relation in group.split('|') {
+      match parse_relation(relation.trim()) {
+        Ok(_relation) => relations.push(_relation),
+        Err(msg) => {
+          return Err(format!(
+            "Invalid Depends/Pre-Depends/Provides field: {}: {}",
+            _dep.trim(),
+            msg
+          ))
+        }
+      }
+    }
+    groups.push(relations);
+  }
+
+  Ok(groups)
+}
+
+fn parse_relation(rel: &str) -> Result<Relation, String> {
+  let mut relation = Relation::default();
+  let name_end = rel
+    .find(|c: char| c.is_whitespace() || c == '(' || c == '[' || c == '<')
+    .unwrap_or(rel.len());
+  let (name, qualifier) = split_qualified_name(&rel[..name_end]);
+  if name.is_empty() {
+    return Err("missing package name".to_string());
+  }
+  relation.name = name.to_string();
+  relation.arch_qualifier = qualifier.map(|q| q.to_string());
+
+  // @ret: content between the opening bracket at the head of @rest and @close,
+  //       and length of the whole enclosed part.
+  let enclosed = |rest: &str, close: char| -> Result<(String, usize), String> {
+    match rest.find(close) {
+      Some(ix) => Ok((rest[1..ix].trim().to_string(), ix + 1)),
+      None => Err(format!("unterminated '{}'", &rest[..1])),
+    }
+  };
+  let terms = |content: &str| {
+    content
+      .split_whitespace()
+      .map(|t| Restriction {
+        negated: t.starts_with('!'),
+        name: t.trim_start_matches('!').to_string(),
+      })
+      .collect::<Vec<_>>()
+  };
+
+  let mut rest = rel[name_end..].trim_start();
+  while !rest.is_empty() {
+    let len = match rest.chars().next().unwrap() {
+      '(' => {
+        let (content, len) = enclosed(rest, ')')?;
+        if relation.version.is_some() {
+          return Err("duplicated version restriction".to_string());
+        }
+        let op_end = content
+          .find(|c: char| c != '<' && c != '>' && c != '=')
+          .unwrap_or(content.len());
+        let version = content[op_end..].trim();
+        if version.is_empty() {
+          return Err(format!("missing version in '({})'", content));
+        }
+        relation.version = Some((RelOp::parse(&content[..op_end])?, version.to_string()));
+        len
+      }
+      '[' => {
+        let (content, len) = enclosed(rest, ']')?;
+        relation.arch_restrictions = terms(&content);
+        len
+      }
+      '<' => {
+        let (content, len) = enclosed(rest, '>')?;
+        relation.profiles.push(terms(&content));
+        len
+      }
+      c => return Err(format!("unexpected '{}'", c)),
+    };
+    rest = rest[len..].trim_start();
+  }
+
+  Ok(relation)
 }
 
 pub fn choose_package(p1: &SourcePackage, p2: &SourcePackage) -> SourcePackage {
@@ -632,13 +1089,13 @@ pub fn resolve_duplication(
       _progress_bar.unwrap().inc(1);
     }
 
-    if hashmap.contains_key(&item.package) {
-      hashmap.insert(
-        item.package.to_owned(),
-        choose_package(&item, &hashmap.get(&item.package).unwrap()),
-      );
+    // the same package of different architectures can co-exist.
+    let key = format!("{}:{}", item.package, item.arch_name());
+    if hashmap.contains_key(&key) {
+      let chosen = choose_package(item, hashmap.get(&key).unwrap());
+      hashmap.insert(key, chosen);
     } else {
-      hashmap.insert(item.package.to_owned(), item.to_owned());
+      hashmap.insert(key, item.to_owned());
     }
   }
 
@@ -704,7 +1161,38 @@ pub fn parse_status(status_str: &str) -> Result<Status, String> {
   })
 }
 
-pub fn read_extended_information(filename: &str) -> Vec<(String, bool)> {
+// stanzas of extended_states as lists of (field, value).
+// continuation lines are kept in the value as they are.
+fn parse_extended_stanzas(extended_str: &str) -> Vec<Vec<(String, String)>> {
+  let mut stanzas = vec![];
+  for parts in extended_str.split("\n\n") {
+    let mut fields: Vec<(String, String)> = vec![];
+    for line in parts.lines() {
+      if line.starts_with(' ') || line.starts_with('\t') {
+        if let Some(last) = fields.last_mut() {
+          last.1 = format!("{}\n{}", last.1, line);
+        }
+        continue;
+      }
+      if let Some((key, value)) = line.split_once(':') {
+        fields.push((key.to_string(), value.trim().to_string()));
+      }
+    }
+    if !fields.is_empty() {
+      stanzas.push(fields);
+    }
+  }
+  stanzas
+}
+
+fn extended_field<'a>(stanza: &'a [(String, String)], key: &str) -> Option<&'a str> {
+  stanza
+    .iter()
+    .find(|(k, _)| k == key)
+    .map(|(_, v)| v.as_str())
+}
+
+pub fn read_extended_information(filename: &str) -> Vec<ExtendedState> {
   let mut ret = vec![];
   let extended_str = match std::fs::read_to_string(filename) {
     Ok(_str) => _str,
@@ -716,22 +1204,97 @@ pub fn read_extended_information(filename: &str) -> Vec<(String, bool)> {
       return vec![];
     }
   };
-  for parts in extended_str.split("\n\n").collect::<Vec<_>>() {
-    let tmp = parts.split("\n").collect::<Vec<_>>();
-    if tmp.len() <= 1 {
-      continue;
-    }
-    let package = tmp[0].split("Package: ").collect::<Vec<_>>()[1];
-    let auto = match tmp[2].split("Auto-Installed: ").collect::<Vec<_>>()[1] {
-      "1" => true,
-      _ => false,
+  for stanza in parse_extended_stanzas(&extended_str) {
+    let package = match extended_field(&stanza, "Package") {
+      Some(_package) => _package,
+      None => continue,
     };
-    ret.push((package.to_string(), auto));
+    let arch = extended_arch(extended_field(&stanza, "Architecture").unwrap_or_default());
+    let auto = extended_field(&stanza, "Auto-Installed") == Some("1");
+    ret.push((package.to_string(), arch, auto));
   }
 
   ret
 }
 
+// extended_states records 'Architecture: all' packages as native ones.
+fn extended_arch(arch: &str) -> String {
+  match arch {
+    "all" | "" => CONFIG.architecture.clone(),
+    _arch => _arch.to_string(),
+  }
+}
+
+// @ret: whether @package is marked as auto-installed in @extended.
+//       packages without the record are regarded as manually installed.
+pub fn auto_installed_in(extended: &[ExtendedState], package: &SourcePackage) -> bool {
+  let arch = extended_arch(&package.arch_name());
+  match extended
+    .iter()
+    .find(|e| e.0 == package.package && e.1 == arch)
+  {
+    Some(_state) => _state.2,
+    None => false,
+  }
+}
+
+pub fn is_auto_installed(package: &SourcePackage) -> bool {
+  auto_installed_in(&EXTENDED_CACHE, package)
+}
+
+// set 'Auto-Installed' of @marks in @filename, keeping other stanzas and fields.
+// the file is replaced atomically.
+pub fn write_extended_information(
+  filename: &str,
+  marks: &[(&SourcePackage, bool)],
+) -> Result<(), String> {
+  let extended_str = std::fs::read_to_string(filename).unwrap_or_default();
+  let mut stanzas = parse_extended_stanzas(&extended_str);
+  for (package, auto) in marks {
+    let arch = extended_arch(&package.arch_name());
+    let auto = if *auto { "1" } else { "0" }.to_string();
+    let found = stanzas.iter_mut().find(|s| {
+      extended_field(s, "Package") == Some(package.package.as_str())
+        && extended_arch(extended_field(s, "Architecture").unwrap_or_default()) == arch
+    });
+    match found {
+      Some(stanza) => match stanza.iter_mut().find(|(k, _)| k == "Auto-Installed") {
+        Some(field) => field.1 = auto,
+        None => stanza.push(("Auto-Installed".to_string(), auto)),
+      },
+      None => stanzas.push(vec![
+        ("Package".to_string(), package.package.clone()),
+        ("Architecture".to_string(), arch),
+        ("Auto-Installed".to_string(), auto),
+      ]),
+    }
+  }
+
+  let content = stanzas
+    .iter()
+    .map(|s| {
+      s.iter()
+        .map(|(k, v)| format!("{}: {}\n", k, v))
+        .collect::<String>()
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+  if let Some(dir) = std::path::Path::new(filename).parent() {
+    if !dir.as_os_str().is_empty() && std::fs::create_dir_all(dir).is_err() {
+      return Err(format!("failed to create directory of {}", filename));
+    }
+  }
+  let tmpname = format!("{}.tmp", filename);
+  match std::fs::write(&tmpname, content) {
+    Ok(()) => {}
+    Err(msg) => return Err(format!("failed to write {}: {}", tmpname, msg)),
+  };
+  match std::fs::rename(&tmpname, filename) {
+    Ok(()) => Ok(()),
+    Err(msg) => Err(format!("failed to replace {}: {}", filename, msg)),
+  }
+}
+
 #[cfg(test)]
 pub mod test {
 
@@ -739,11 +1302,15 @@ pub mod test {
   fn test_package_source_from_row() {
     let sample = std::fs::read_to_string("test/sample-index").unwrap();
     let psources =
-      super::SourcePackage::from_raw(&sample, "jp.hogehoge.com_dists_focal-main").unwrap();
+      super::SourcePackage::from_raw(&sample, "jp.hogehoge.com_dists_focal_main").unwrap();
     let dpkg = &psources[0];
     assert_eq!(psources.len(), 3);
     assert_eq!(dpkg.package, "dpkg");
-    assert_eq!(dpkg.pre_depends["libzstd1"].as_ref().unwrap(), "1.3.2");
+    assert_eq!(dpkg.pre_depends[4][0].name, "libzstd1");
+    assert_eq!(
+      dpkg.pre_depends[4][0].version,
+      Some((super::RelOp::GE, "1.3.2".to_string()))
+    );
     assert_eq!(dpkg.arch[0], super::Arch::AMD64);
     assert_eq!(dpkg.version, "1.19.7ubuntu3");
     assert_eq!(dpkg.essential, true);
@@ -756,13 +1323,25 @@ pub mod test {
       dpkg.original_maintainer,
       "Dpkg Developers <debian-dpkg@lists.debian.org>"
     );
-    assert_eq!(dpkg.suggests.contains(&"apt".to_string()), true);
-    assert_eq!(dpkg.suggests.contains(&"debsig-verify".to_string()), true);
+    assert_eq!(super::format_depends(&dpkg.suggests), "apt, debsig-verify");
     assert_eq!(
       dpkg.filename,
       "pool/main/d/dpkg/dpkg_1.19.7ubuntu3_amd64.deb"
     );
     assert_eq!(dpkg.chksum_md5, "f595c79475d3c2ac808eaac389071c35");
+    assert_eq!(dpkg.chksum_sha1, "b9cb6b292865ec85bca1021085bc0e81e160e676");
+    assert_eq!(
+      dpkg.chksum_sha256,
+      "76132be95c7199f902767fb329e0f33210ac5b5b1816746543bc75f795d9a37c"
+    );
+    assert_eq!(dpkg.size, 1127856);
+    let apt = &psources[1];
+    assert_eq!(apt.recommends[0][0].name, "ca-certificates");
+    assert_eq!(apt.suggests[1].len(), 3);
+    assert_eq!(
+      apt.suggests[2][0].version,
+      Some((super::RelOp::GE, "1.17.2".to_string()))
+    );
     assert_eq!(
       dpkg.description,
       "Debian package management system\nwaiwai second sentence.\nuouo fish life."
@@ -807,7 +1386,7 @@ pub mod test {
   fn test_package_resolve_duplication() {
     let sample = std::fs::read_to_string("test/sample-duplicated-index").unwrap();
     let psources =
-      super::SourcePackage::from_raw(&sample, "jp.hogehgoe.com_dists_focal-main").unwrap();
+      super::SourcePackage::from_raw(&sample, "jp.hogehgoe.com_dists_focal_main").unwrap();
     assert_eq!(psources.len(), 3);
     let resolved = super::resolve_duplication(&psources, None).unwrap();
     assert_eq!(resolved.len(), 1);
@@ -817,16 +1396,155 @@ pub mod test {
     assert_eq!(dpkg.version, "1.20.7ubuntu3");
   }
 
+  #[test]
+  fn test_to_pool_uri() {
+    use super::SourcePackage;
+    let raw =
+      "Package: foo\nArchitecture: amd64\nVersion: 1\nFilename: pool/main/f/foo/foo_1_amd64.deb\n";
+    let list = "http:__a.example.com_debian__dists_focal-updates_non-free_binary-amd64";
+    let other = "https:__b.example.com_debian__dists_focal_main_binary-amd64";
+    // same package in several repositories is fetched from the one it is read from.
+    let foo = &SourcePackage::from_raw(raw, list).unwrap()[0];
+    assert_eq!(
+      foo.to_pool_uri().unwrap(),
+      "http://a.example.com/debian/pool/main/f/foo/foo_1_amd64.deb"
+    );
+    let foo = &SourcePackage::from_raw(raw, other).unwrap()[0];
+    assert_eq!(
+      foo.to_pool_uri().unwrap(),
+      "https://b.example.com/debian/pool/main/f/foo/foo_1_amd64.deb"
+    );
+    assert!(SourcePackage::from_raw(raw, "").unwrap()[0]
+      .to_pool_uri()
+      .is_err());
+  }
+
+  #[test]
+  fn test_arch() {
+    use super::{split_qualified_name, Arch, SourcePackage};
+    assert_eq!(Arch::from_name("arm64"), Arch::ARM64);
+    assert_eq!(Arch::from_name("hurd-i386"), Arch::HURD_I386);
+    assert_eq!(Arch::from_name("pdp11"), Arch::UNKNOWN);
+    assert_eq!(Arch::PPC64EL.to_string(), "ppc64el");
+    assert_eq!(split_qualified_name("libc6:i386"), ("libc6", Some("i386")));
+    assert_eq!(split_qualified_name("libc6"), ("libc6", None));
+
+    let native = crate::config::CONFIG.architecture.clone();
+    let foreign = if native == "i386" { "amd64" } else { "i386" };
+    let p = SourcePackage {
+      package: "libc6".to_string(),
+      arch: vec![Arch::from_name(foreign)],
+      ..Default::default()
+    };
+    assert_eq!(p.qualified_name(), format!("libc6:{}", foreign));
+    assert!(p.matches_arch(foreign));
+    assert!(!p.matches_arch(&native));
+    let all = SourcePackage {
+      arch: vec![Arch::ALL],
+      ..p.clone()
+    };
+    assert_eq!(all.qualified_name(), "libc6");
+    assert!(all.matches_arch(foreign) && all.matches_arch(&native));
+
+    // packages of different architectures are not duplicates.
+    let resolved = super::resolve_duplication(&vec![p.clone(), all.clone(), p], None).unwrap();
+    assert_eq!(resolved.len(), 2);
+  }
+
+  #[test]
+  fn test_multi_arch() {
+    use super::{Arch, MultiArch, SourcePackage};
+    let native = crate::config::CONFIG.architecture.clone();
+    let foreign = if native == "i386" { "amd64" } else { "i386" };
+    let index = std::fs::read_to_string("test/sample-index").unwrap();
+    let dpkg = &SourcePackage::from_raw(&index, "").unwrap()[0];
+    assert_eq!(dpkg.multi_arch, MultiArch::FOREIGN);
+
+    let package = |arch: &str, multi_arch| SourcePackage {
+      package: "libfoo".to_string(),
+      arch: vec![Arch::from_name(arch)],
+      multi_arch,
+      ..Default::default()
+    };
+    // plain dependency is satisfied by the same architecture, or 'foreign' ones.
+    assert!(package(&native, MultiArch::SAME).satisfies_arch(None, &native));
+    assert!(!package(foreign, MultiArch::SAME).satisfies_arch(None, &native));
+    assert!(package(foreign, MultiArch::FOREIGN).satisfies_arch(None, &native));
+    assert!(package("all", MultiArch::NO).satisfies_arch(None, "all"));
+    assert!(!package("all", MultiArch::NO).satisfies_arch(None, foreign));
+    // ':any' needs 'allowed'.
+    assert!(package(foreign, MultiArch::ALLOWED).satisfies_arch(Some("any"), &native));
+    assert!(!package(&native, MultiArch::NO).satisfies_arch(Some("any"), &native));
+    assert!(!package(&native, MultiArch::FOREIGN).satisfies_arch(Some("any"), &native));
+    // ':native' and explicit architecture.
+    assert!(package(&native, MultiArch::SAME).satisfies_arch(Some("native"), foreign));
+    assert!(!package(foreign, MultiArch::SAME).satisfies_arch(Some("native"), foreign));
+    assert!(package(foreign, MultiArch::NO).satisfies_arch(Some(foreign), &native));
+  }
+
   #[test]
   fn test_parse_depends() {
+    use super::{parse_depends, RelOp, Restriction};
     let dep1 = "libc6 (>= 2.15)";
     let dep2 = "libbz2-1.0";
-    let pdep1 = super::parse_depends(dep1).unwrap();
-    let pdep2 = super::parse_depends(dep2).unwrap();
-    assert_eq!(pdep1.0, "libc6");
-    assert_eq!(pdep1.1.unwrap(), "2.15");
-    assert_eq!(pdep2.0, "libbz2-1.0");
-    assert_eq!(pdep2.1, None);
+    let pdep1 = &parse_depends(dep1).unwrap()[0][0];
+    let pdep2 = &parse_depends(dep2).unwrap()[0][0];
+    assert_eq!(pdep1.name, "libc6");
+    assert_eq!(pdep1.version, Some((RelOp::GE, "2.15".to_string())));
+    assert_eq!(pdep2.name, "libbz2-1.0");
+    assert_eq!(pdep2.version, None);
+
+    let groups = parse_depends(
+      "python3:any (<< 3.9), default-mta | mail-transport-agent (>>1:2), \
+       libfoo (=1.2-3) [amd64 !i386] <!nocheck> <stage1 cross>,",
+    )
+    .unwrap();
+    assert_eq!(groups.len(), 3);
+    assert_eq!(groups[0][0].name, "python3");
+    assert_eq!(groups[0][0].arch_qualifier, Some("any".to_string()));
+    assert_eq!(groups[0][0].version, Some((RelOp::LT, "3.9".to_string())));
+    assert_eq!(groups[1].len(), 2);
+    assert_eq!(groups[1][1].version, Some((RelOp::GT, "1:2".to_string())));
+    let libfoo = &groups[2][0];
+    assert_eq!(libfoo.version, Some((RelOp::EQ, "1.2-3".to_string())));
+    assert_eq!(
+      libfoo.arch_restrictions[1],
+      Restriction {
+        negated: true,
+        name: "i386".to_string()
+      }
+    );
+    assert_eq!(libfoo.profiles.len(), 2);
+    assert_eq!(libfoo.profiles[1].len(), 2);
+    assert_eq!(
+      libfoo.to_string(),
+      "libfoo (= 1.2-3) [amd64 !i386] <!nocheck> <stage1 cross>"
+    );
+
+    // restrictions and version relations
+    assert!(libfoo.applies_to_arch("amd64"));
+    assert!(!libfoo.applies_to_arch("arm64"));
+    let not_i386 = &parse_depends("foo [!i386]").unwrap()[0][0];
+    assert!(not_i386.applies_to_arch("arm64") && !not_i386.applies_to_arch("i386"));
+    assert!(libfoo.applies_to_profiles(&[]));
+    assert!(!libfoo.applies_to_profiles(&["nocheck".to_string()]));
+    assert!(libfoo.satisfied_by_version("1.2-3"));
+    assert!(!libfoo.satisfied_by_version("1.2-4"));
+    assert!(groups[0][0].satisfied_by_version("3.8.2-0ubuntu2"));
+    assert!(!groups[0][0].satisfied_by_version("3.9"));
+    assert_eq!(
+      parse_depends("foo (< 1)").unwrap()[0][0]
+        .version
+        .as_ref()
+        .unwrap()
+        .0,
+      RelOp::LE
+    );
+
+    assert!(parse_depends("foo (>= 1.0").is_err());
+    assert!(parse_depends("foo (~ 1.0)").is_err());
+    assert!(parse_depends("foo (>=)").is_err());
+    assert!(parse_depends("foo | , bar").is_err());
   }
 
   #[test]
@@ -834,7 +1552,7 @@ pub mod test {
     use crate::source::*;
     let sample_stat_str = std::fs::read_to_string("test/sample-dpkg-status").unwrap();
     //let items = SourcePackage::from_row(&sample_stat_str).unwrap();
-    let items = match SourcePackage::from_raw(&sample_stat_str, "jp.hogehoge.com_dists_focal-main")
+    let items = match SourcePackage::from_raw(&sample_stat_str, "jp.hogehoge.com_dists_focal_main")
     {
       Ok(a) => a,
       Err(msg) => {
@@ -866,12 +1584,65 @@ pub mod test {
     assert_eq!(
       res,
       vec![
-        ("libclass-method-modifiers-perl".to_string(), true),
-        ("libsub-name-perl".to_string(), true),
-        ("librole-tiny-perl".to_string(), false),
-        ("libimport-into-perl".to_string(), true),
+        (
+          "libclass-method-modifiers-perl".to_string(),
+          "amd64".to_string(),
+          true
+        ),
+        ("libsub-name-perl".to_string(), "amd64".to_string(), true),
+        ("librole-tiny-perl".to_string(), "amd64".to_string(), false),
+        ("libimport-into-perl".to_string(), "amd64".to_string(), true),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_write_extended_information() {
+    use super::{
+      auto_installed_in, read_extended_information, write_extended_information, SourcePackage,
+    };
+    let native = crate::config::CONFIG.architecture.clone();
+    let filename = std::env::temp_dir()
+      .join(format!("rapt-extended_states-{}", std::process::id()))
+      .to_string_lossy()
+      .to_string();
+    std::fs::write(
+      &filename,
+      "Package: foo\nArchitecture: amd64\nAuto-Installed: 1\n\n\
+       Package: foo\nArchitecture: i386\nAuto-Installed: 1\nX-Note: kept\n",
+    )
+    .unwrap();
+    let packages = SourcePackage::from_raw(
+      "Package: foo\nArchitecture: i386\nVersion: 1\n\n\
+       Package: bar\nArchitecture: all\nVersion: 1\n\n\
+       Package: foo\nArchitecture: amd64\nVersion: 1\n",
+      "",
+    )
+    .unwrap();
+    write_extended_information(&filename, &[(&packages[0], false), (&packages[1], true)]).unwrap();
+    assert_eq!(
+      std::fs::read_to_string(&filename).unwrap(),
+      format!(
+        "Package: foo\nArchitecture: amd64\nAuto-Installed: 1\n\n\
+         Package: foo\nArchitecture: i386\nAuto-Installed: 0\nX-Note: kept\n\n\
+         Package: bar\nArchitecture: {}\nAuto-Installed: 1\n",
+        native
+      )
+    );
+    assert_eq!(
+      read_extended_information(&filename),
+      vec![
+        ("foo".to_string(), "amd64".to_string(), true),
+        ("foo".to_string(), "i386".to_string(), false),
+        ("bar".to_string(), native.clone(), true),
       ]
     );
+    // the record of the other architecture doesn't apply.
+    let extended = read_extended_information(&filename);
+    assert!(!auto_installed_in(&extended, &packages[0]));
+    assert!(auto_installed_in(&extended, &packages[1]));
+    assert!(auto_installed_in(&extended, &packages[2]));
+    std::fs::remove_file(&filename).unwrap();
   }
 
   #[test]
@@ -880,11 +1651,55 @@ pub mod test {
     let index_str = std::fs::read_to_string("test/sample-index-provides").unwrap();
     let dbus = &SourcePackage::from_raw(&index_str, "").unwrap()[0];
     assert_eq!(
-      dbus.provides,
+      dbus
+        .provides
+        .iter()
+        .map(|p| p.name.clone())
+        .collect::<Vec<_>>(),
       vec![
         "dbus-session-bus".to_string(),
         "default-dbus-session-bus".to_string()
       ]
     );
   }
+
+  #[test]
+  fn test_provides() {
+    use super::{build_provider_index, find_providers, parse_depends};
+    let sample = std::fs::read_to_string("test/sample-index").unwrap();
+    let items = super::SourcePackage::from_raw(&sample, "").unwrap();
+    let apt = &items[1];
+    assert_eq!(
+      apt.provides[0].version,
+      Some((super::RelOp::EQ, "2.0.2".to_string()))
+    );
+
+    let index = build_provider_index(&items);
+    let providers = find_providers(&items, &index, "apt-transport-https");
+    assert_eq!(providers.len(), 1);
+    assert_eq!(providers[0].package, "apt");
+    assert!(find_providers(&items, &index, "apt").is_empty());
+
+    // versioned Provides satisfies versioned relationship.
+    let relation = |s: &str| parse_depends(s).unwrap().remove(0).remove(0);
+    assert!(relation("apt-transport-https").satisfied_by(apt));
+    assert!(relation("apt-transport-https (>= 2.0)").satisfied_by(apt));
+    assert!(!relation("apt-transport-https (<< 1.5~alpha4~)").satisfied_by(apt));
+    assert!(relation("apt (= 2.0.2)").satisfied_by(apt));
+
+    // unversioned one satisfies only unversioned relationship.
+    let index_str = std::fs::read_to_string("test/sample-index-provides").unwrap();
+    let dbus = &super::SourcePackage::from_raw(&index_str, "").unwrap()[0];
+    assert!(relation("dbus-session-bus").satisfied_by(dbus));
+    assert!(!relation("dbus-session-bus (>= 1.0)").satisfied_by(dbus));
+    let foo = &super::SourcePackage::from_raw(
+      "Package: foo\nProvides: bar (>= 1.0), baz (= 2.0)\nVersion: 1\n",
+      "",
+    )
+    .unwrap()[0];
+    assert_eq!(foo.package, "foo");
+    assert_eq!(foo.version, "1");
+    assert_eq!(foo.provides.len(), 1);
+    assert_eq!(foo.provides[0].name, "baz");
+  }
 }
@@ -0,0 +1,295 @@
+use crate::chksum;
+use crate::config::CONFIG;
+use indicatif::ProgressBar;
+use reqwest::{header, Client, StatusCode};
+use std::io::prelude::*;
+
+// method to retrieve files from a repository, selected by the scheme of the URI.
+pub trait Transport {
+  // fetch whole content of @uri.
+  // @ret: None if the file doesn't exist in the repository.
+  fn fetch(&self, uri: &str, progress_bar: Option<&ProgressBar>)
+    -> Result<Option<Vec<u8>>, String>;
+
+  // fetch @uri into @path. if @path already has some content, the transport
+  // may resume from there. @hasher must be fed with the content of @path,
+  // and it is reset if the transport has to start over.
+  // @ret: size of the content actually fetched
+  fn fetch_to_file(
+    &self,
+    uri: &str,
+    path: &str,
+    hasher: &mut chksum::Hasher,
+    progress_bar: Option<&ProgressBar>,
+  ) -> Result<u64, String>;
+
+  // @ret: path of the file which can be used in place without fetching.
+  fn local_path(&self, _uri: &str) -> Option<String> {
+    None
+  }
+}
+
+pub fn get_transport(uri: &str) -> Result<Box<dyn Transport>, String> {
+  let scheme = match uri.find("://") {
+    Some(ix) => &uri[..ix],
+    None => return Err(format!("invalid uri: {}", uri)),
+  };
+  match scheme {
+    "http" | "https" => Ok(Box::new(HttpTransport::new(&CONFIG.ca_info)?)),
+    "file" => Ok(Box::new(FileTransport { copy: false })),
+    "copy" => Ok(Box::new(FileTransport { copy: true })),
+    _ => Err(format!("unsupported transport: {}", scheme)),
+  }
+}
+
+pub struct HttpTransport {
+  client: Client,
+}
+
+impl HttpTransport {
+  pub fn new(ca_info: &Option<String>) -> Result<Self, String> {
+    let mut builder = Client::builder();
+    if let Some(ca_info) = ca_info {
+      let pem = match std::fs::read(ca_info) {
+        Ok(_pem) => _pem,
+        Err(msg) => return Err(format!("failed to open CA bundle {}: {}", ca_info, msg)),
+      };
+      let cert = match reqwest::Certificate::from_pem(&pem) {
+        Ok(_cert) => _cert,
+        Err(msg) => return Err(format!("invalid CA bundle {}: {}", ca_info, msg)),
+      };
+      builder = builder.add_root_certificate(cert);
+    }
+    match builder.build() {
+      Ok(client) => Ok(Self { client }),
+      Err(msg) => Err(format!("failed to initialize http client: {}", msg)),
+    }
+  }
+}
+
+impl Transport for HttpTransport {
+  fn fetch(
+    &self,
+    uri: &str,
+    progress_bar: Option<&ProgressBar>,
+  ) -> Result<Option<Vec<u8>>, String> {
+    let task = async {
+      let mut download = match self.client.get(uri).send().await {
+        Ok(_download) => _download,
+        Err(msg) => return Err(format!("error while fetching {}: {}", uri, msg)),
+      };
+      if download.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+      }
+      if !download.status().is_success() {
+        return Err(format!(
+          "error while fetching {}: error code={}",
+          uri,
+          download.status().as_str()
+        ));
+      }
+
+      if let Some(progress_bar) = progress_bar {
+        progress_bar.set_message(uri.to_string());
+        progress_bar.set_length(download.content_length().unwrap_or(0));
+        progress_bar.set_position(0);
+      }
+      let mut buf: Vec<u8> = vec![];
+      loop {
+        match download.chunk().await {
+          Ok(Some(chunk)) => {
+            if let Some(progress_bar) = progress_bar {
+              progress_bar.inc(chunk.len() as u64);
+            }
+            buf.extend_from_slice(&chunk);
+          }
+          Ok(None) => break,
+          Err(msg) => return Err(format!("error while fetching {}: {}", uri, msg)),
+        }
+      }
+      if let Some(progress_bar) = progress_bar {
+        progress_bar.finish();
+      }
+
+      Ok(Some(buf))
+    };
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(task)
+  }
+
+  fn fetch_to_file(
+    &self,
+    uri: &str,
+    path: &str,
+    hasher: &mut chksum::Hasher,
+    progress_bar: Option<&ProgressBar>,
+  ) -> Result<u64, String> {
+    let task = async {
+      let resumed_size = hasher.size();
+      let mut req = self.client.get(uri);
+      if resumed_size != 0 {
+        req = req.header(header::RANGE, format!("bytes={}-", resumed_size));
+      }
+      let mut download = match req.send().await {
+        Ok(_download) => _download,
+        Err(msg) => return Err(format!("error while fetching {}: {}", uri, msg)),
+      };
+      let mut output = match download.status() {
+        StatusCode::PARTIAL_CONTENT => match std::fs::OpenOptions::new().append(true).open(path) {
+          Ok(_output) => _output,
+          Err(msg) => return Err(format!("failed to open {}: {}", path, msg)),
+        },
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+          // partial file already has whole content.
+          return Ok(0);
+        }
+        status if status.is_success() => {
+          // server doesn't support Range: start over.
+          *hasher = chksum::Hasher::new();
+          match std::fs::File::create(path) {
+            Ok(_output) => _output,
+            Err(msg) => return Err(format!("failed to create {}: {}", path, msg)),
+          }
+        }
+        status => {
+          return Err(format!(
+            "error while fetching {}: error code={}",
+            uri,
+            status.as_str()
+          ))
+        }
+      };
+
+      if let Some(progress_bar) = progress_bar {
+        progress_bar.set_message(uri.to_string());
+        progress_bar.set_length(hasher.size() + download.content_length().unwrap_or(0));
+        progress_bar.set_position(hasher.size());
+      }
+      let mut fetched_size = 0;
+      loop {
+        let chunk = match download.chunk().await {
+          Ok(Some(_chunk)) => _chunk,
+          Ok(None) => break,
+          Err(msg) => return Err(format!("error while fetching {}: {}", uri, msg)),
+        };
+        if let Some(progress_bar) = progress_bar {
+          progress_bar.inc(chunk.len() as u64);
+        }
+        hasher.update(&chunk);
+        fetched_size += chunk.len() as u64;
+        if let Err(msg) = output.write_all(&chunk) {
+          return Err(format!("failed to write {}: {}", path, msg));
+        }
+      }
+
+      if let Some(progress_bar) = progress_bar {
+        progress_bar.finish();
+      }
+      Ok(fetched_size)
+    };
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(task)
+  }
+}
+
+// file:// reads files in place, and copy:// copies them into archive/.
+pub struct FileTransport {
+  copy: bool,
+}
+
+impl FileTransport {
+  fn to_path(uri: &str) -> String {
+    let ix = uri.find("://").unwrap();
+    uri[ix + 3..].to_string()
+  }
+}
+
+impl Transport for FileTransport {
+  fn fetch(
+    &self,
+    uri: &str,
+    progress_bar: Option<&ProgressBar>,
+  ) -> Result<Option<Vec<u8>>, String> {
+    let path = Self::to_path(uri);
+    if !std::path::Path::new(&path).exists() {
+      return Ok(None);
+    }
+    match std::fs::read(&path) {
+      Ok(content) => {
+        if let Some(progress_bar) = progress_bar {
+          progress_bar.set_message(uri.to_string());
+          progress_bar.set_length(content.len() as u64);
+          progress_bar.set_position(content.len() as u64);
+          progress_bar.finish();
+        }
+        Ok(Some(content))
+      }
+      Err(msg) => Err(format!("failed to read {}: {}", path, msg)),
+    }
+  }
+
+  fn fetch_to_file(
+    &self,
+    uri: &str,
+    path: &str,
+    hasher: &mut chksum::Hasher,
+    progress_bar: Option<&ProgressBar>,
+  ) -> Result<u64, String> {
+    // local copy is cheap enough to start over every time.
+    let content = match self.fetch(uri, progress_bar)? {
+      Some(_content) => _content,
+      None => return Err(format!("file not found: {}", Self::to_path(uri))),
+    };
+    *hasher = chksum::Hasher::new();
+    hasher.update(&content);
+    match std::fs::write(path, &content) {
+      Ok(()) => Ok(content.len() as u64),
+      Err(msg) => Err(format!("failed to write {}: {}", path, msg)),
+    }
+  }
+
+  fn local_path(&self, uri: &str) -> Option<String> {
+    if self.copy {
+      None
+    } else {
+      Some(Self::to_path(uri))
+    }
+  }
+}
+
+#[cfg(test)]
+pub mod test {
+  #[test]
+  fn test_file_transport() {
+    let cwd = std::env::current_dir().unwrap();
+    let uri = format!(
+      "file://{}/test/repo/dists/focal/Release",
+      cwd.to_str().unwrap()
+    );
+    let transport = super::get_transport(&uri).unwrap();
+    let content = transport.fetch(&uri, None).unwrap().unwrap();
+    assert_eq!(
+      content,
+      std::fs::read("test/repo/dists/focal/Release").unwrap()
+    );
+    assert_eq!(
+      transport.local_path(&uri),
+      Some(format!(
+        "{}/test/repo/dists/focal/Release",
+        cwd.to_str().unwrap()
+      ))
+    );
+    assert!(transport
+      .fetch(&format!("{}.missing", uri), None)
+      .unwrap()
+      .is_none());
+
+    let copy_uri = uri.replace("file://", "copy://");
+    let transport = super::get_transport(&copy_uri).unwrap();
+    assert_eq!(transport.local_path(&copy_uri), None);
+
+    assert!(super::get_transport("ftp://example.com/").is_err());
+  }
+}
@@ -3,22 +3,33 @@ use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::sync::mpsc;
 use std::thread;
 
+use crate::config::CONFIG;
+use crate::release::Release;
 use crate::source::SourcePackage;
 use crate::{cache, dpkg, fetcher, lock, slist, source};
 
-pub fn do_update() {
+pub fn do_update(trusted_dir: &str, source_parts: &str) {
   log::trace!("do_update()");
 
   let mut package_items = vec![];
 
-  // read sources.list
-  let sources = match slist::parse_source_file("sources.list") {
-    Ok(_items) => _items,
+  // read sources.list and sources.list.d/
+  let sources = match slist::load_sources(slist::DEFAULT_SOURCE_LIST, source_parts) {
+    Ok(_items) => _items
+      .into_iter()
+      .filter(|s| s.is_binary() && s.has_target("Packages"))
+      .collect::<Vec<_>>(),
     Err(msg) => {
       println!("{}", msg);
       return;
     }
   };
+  // index of each configured architecture is fetched separately.
+  let archs = CONFIG.architectures();
+  let targets = sources
+    .iter()
+    .map(|s| (s.clone(), s.index_archs(&archs)))
+    .collect::<Vec<_>>();
 
   let lock = match lock::get_lock(lock::Lock::LIST) {
     Ok(_lock) => _lock,
@@ -33,11 +44,18 @@ pub fn do_update() {
   println!("Fetching indexes... ");
 
   let mut fetched_amount = 0;
-  match fetch_indexes_thread(&sources) {
+  match fetch_indexes_thread(&targets, trusted_dir) {
     Ok((fetched_sizes, mut items)) => {
       for s in fetched_sizes {
         fetched_amount += s;
       }
+      let keeps = targets
+        .iter()
+        .flat_map(|(source, archs)| archs.iter().map(move |arch| source.to_filename(arch)))
+        .collect::<Vec<_>>();
+      if let Err(msg) = cache::remove_stale_lists(&keeps) {
+        println!("{}", msg);
+      }
       package_items.append(&mut items);
     }
     Err(msg) => {
@@ -86,8 +104,11 @@ pub fn do_update() {
   }
 }
 
+// @targets: sources and architectures of their indexes.
+// Release of each source is fetched and verified once, and shared by its indexes.
 pub fn fetch_indexes_thread(
-  sources: &Vec<slist::Source>,
+  targets: &[(slist::Source, Vec<String>)],
+  trusted_dir: &str,
 ) -> Result<(Vec<u64>, Vec<SourcePackage>), String> {
   let mut handles = vec![];
   let mut package_items = vec![];
@@ -99,42 +120,43 @@ pub fn fetch_indexes_thread(
     .template("Get: [{bar:40.cyan/blue}] {bytes}/{total_bytes} - {msg}")
     .progress_chars("#>-");
 
-  for ix in 0..sources.len() {
-    let source = sources[ix].clone();
+  for (source, archs) in targets {
+    let (source, archs) = (source.clone(), archs.clone());
+    let trusted_dir = trusted_dir.to_string();
     let tx = tx.clone();
-    let progress_bar = progress_bars.add(ProgressBar::new(9999999999));
-    progress_bar.set_style(progress_style.clone());
+    let arch_progress_bars = archs
+      .iter()
+      .map(|_| {
+        let progress_bar = progress_bars.add(ProgressBar::new(9999999999));
+        progress_bar.set_style(progress_style.clone());
+        progress_bar
+      })
+      .collect::<Vec<_>>();
 
     let handle = thread::spawn(move || {
-      let raw_index = match fetcher::fetch_index(&source, Some(progress_bar)) {
-        Ok(_raw_index) => _raw_index,
+      // never write index into lists/ unless its Release is signed by a trusted key.
+      let release = match fetcher::fetch_release(&source, &trusted_dir) {
+        Ok(_release) => _release,
         Err(msg) => {
-          println!("{}", msg);
+          for progress_bar in arch_progress_bars {
+            progress_bar.abandon();
+            tx.send(Err(msg.clone())).unwrap();
+          }
           return;
         }
       };
-      match cache::write_cache_raw(&raw_index, &source) {
-        Ok(()) => {}
-        Err(msg) => {
-          println!("{}", msg);
-          return;
-        }
-      }
-      let fetched_size = raw_index.len() as u64;
-      match source::SourcePackage::from_raw(&raw_index, &source.to_filename()) {
-        Ok(mut _items) => {
-          tx.send(Ok((fetched_size, _items))).unwrap();
-        }
-        Err(msg) => {
-          tx.send(Err(msg)).unwrap();
-        }
+      for (arch, progress_bar) in archs.iter().zip(arch_progress_bars) {
+        let result = fetch_index_target(&source, arch, release.as_ref(), progress_bar);
+        tx.send(result).unwrap();
       }
     });
     handles.push(handle);
   }
 
   progress_bars.join().unwrap();
-  for handle in handles {
+  // receive every result so that no worker is left sending to the closed channel.
+  let mut first_err = None;
+  for _ in targets.iter().flat_map(|(_, archs)| archs) {
     match rx.recv().unwrap() {
       Ok((fetched_size, mut item)) => {
         package_items.append(&mut item);
@@ -142,11 +164,44 @@ pub fn fetch_indexes_thread(
       }
       Err(msg) => {
         println!("{}", msg);
-        return Err(msg);
+        first_err.get_or_insert(msg);
       }
     }
+  }
+  for handle in handles {
     handle.join().unwrap();
   }
 
-  Ok((fetched_sizes, package_items))
+  match first_err {
+    Some(msg) => Err(msg),
+    None => Ok((fetched_sizes, package_items)),
+  }
+}
+
+// fetch the index of @arch in @source, verified by its @release, and write it into lists/.
+// @ret: (fetched size, packages in the index)
+fn fetch_index_target(
+  source: &slist::Source,
+  arch: &str,
+  release: Option<&Release>,
+  progress_bar: ProgressBar,
+) -> Result<(u64, Vec<SourcePackage>), String> {
+  // foreign architecture may not be provided by the repository.
+  if let Some(release) = release {
+    if !source.is_flat()
+      && !release.architectures.is_empty()
+      && !release.architectures.iter().any(|a| a == arch)
+    {
+      log::info!("{} doesn't provide {}", source.info(), arch);
+      progress_bar.finish_and_clear();
+      return Ok((0, vec![]));
+    }
+  }
+  // index is checked against hashes in the Release before decompressed.
+  let raw_index = fetcher::fetch_index(source, arch, release, Some(progress_bar))?;
+  cache::write_cache_raw(&raw_index, source, arch)?;
+  let fetched_size = raw_index.len() as u64;
+  let items = source::SourcePackage::from_raw(&raw_index, &source.to_filename(arch))?;
+
+  Ok((fetched_size, items))
 }
@@ -1,7 +1,14 @@
-use crate::{cache, dpkg, install, source};
+use crate::dpkg::DependencyFlags;
+use crate::edsp::{self, Solver};
+use crate::resolver::Constraints;
+use crate::source::SourcePackage;
+use crate::{cache, dpkg, install, resolver, source};
 use indicatif::{ProgressBar, ProgressStyle};
 
-pub fn do_upgrade() {
+// upgrade installed packages to their candidates.
+// with @full, new packages may be installed and installed ones may be removed for upgrades.
+// otherwise, such upgrades are kept back and new Recommends/Suggests are not installed.
+pub fn do_upgrade(flags: DependencyFlags, solver: &Solver, full: bool) {
   let progress_bar = ProgressBar::new(0);
   progress_bar.set_style(
     ProgressStyle::default_bar().template("Checking dpkg status       : {bar:40} {msg}"),
@@ -15,14 +22,56 @@ pub fn do_upgrade() {
       return;
     }
   };
-  let upgradable_items = cache::search_cache_with_names(
+  // held packages stay at their versions.
+  let (mut kept_back, upgradable_items_info): (Vec<_>, Vec<_>) =
+    upgradable_items_info.into_iter().partition(|i| i.is_held());
+  let mut upgradable_items = cache::search_cache_with_names(
     &upgradable_items_info
       .iter()
-      .map(|i| i.package.clone())
+      .map(|i| i.arch_qualified_name())
       .collect::<Vec<_>>(),
   );
 
-  match install::install_packages(upgradable_items.iter().map(|u| u).collect::<Vec<_>>()) {
+  let flags = if full {
+    flags
+  } else {
+    DependencyFlags {
+      recommends: false,
+      suggests: false,
+    }
+  };
+  if !full {
+    let (held_back, upgradables) = match keep_back(upgradable_items, flags, solver) {
+      Ok(_partition) => _partition,
+      Err(msg) => {
+        println!("{}", msg);
+        return;
+      }
+    };
+    kept_back.extend(held_back);
+    upgradable_items = upgradables;
+  }
+  if !kept_back.is_empty() {
+    print!("The following packages have been kept back: \n  ");
+    for kp in &kept_back {
+      print!("{} ", kp.qualified_name());
+    }
+    println!();
+  }
+  if upgradable_items.is_empty() {
+    println!(
+      "0 upgraded, 0 newly installed, 0 to remove and {} not upgraded.",
+      kept_back.len()
+    );
+    return;
+  }
+
+  match install::install_packages(
+    upgradable_items.iter().collect::<Vec<_>>(),
+    flags,
+    solver,
+    full,
+  ) {
     Ok(_) => {}
     Err(msg) => {
       println!("{}", msg);
@@ -30,3 +79,31 @@ pub fn do_upgrade() {
     }
   }
 }
+
+// split @upgradables into ones kept back and the others, which can be upgraded together
+// without new installations or removals according to @solver.
+fn keep_back(
+  upgradables: Vec<SourcePackage>,
+  flags: DependencyFlags,
+  solver: &Solver,
+) -> Result<(Vec<SourcePackage>, Vec<SourcePackage>), String> {
+  let (installed, cached) = (&*source::DPKG_CACHE, &*source::CACHE);
+  let constraints = Constraints {
+    forbid_new_install: true,
+    forbid_remove: true,
+  };
+  let solution = match solver {
+    Solver::INTERNAL => resolver::upgrade(&upgradables, installed, cached, flags, constraints)?,
+    Solver::EXTERNAL(solver) => edsp::upgrade(solver, installed, cached, flags, constraints)?,
+    // scenarios are dumped as requested.
+    Solver::DUMP(_) => return Ok((vec![], upgradables)),
+  };
+  let upgraded = |u: &SourcePackage| {
+    solution
+      .upgrades
+      .iter()
+      .any(|s| s.arch_qualified_name() == u.arch_qualified_name() && s.version == u.version)
+  };
+
+  Ok(upgradables.into_iter().partition(|u| !upgraded(u)))
+}